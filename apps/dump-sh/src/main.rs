@@ -67,6 +67,10 @@ struct Opt {
     #[structopt(short, long)]
     dump_code: bool,
 
+    /// List the shapes that use each engine trampoline
+    #[structopt(short, long)]
+    trampolines: bool,
+
     /// Run a custom action
     #[structopt(short, long)]
     custom: bool,
@@ -91,6 +95,7 @@ fn main() -> Fallible<()> {
     };
     TermLogger::init(level, Config::default())?;
 
+    let mut trampoline_usage = HashMap::new();
     for &fid in &inputs {
         let label = catalog.file_label(fid)?;
         let game = label.split(':').last().unwrap();
@@ -99,7 +104,12 @@ fn main() -> Fallible<()> {
         let data = catalog.read_sync(fid)?;
         let shape = RawShape::from_bytes(&data)?;
 
-        if opt.show_all {
+        if opt.trampolines {
+            shape.collect_trampoline_usage(
+                &format!("{}:{}", game, meta.name),
+                &mut trampoline_usage,
+            );
+        } else if opt.show_all {
             for (i, instr) in shape.instrs.iter().enumerate() {
                 println!("{:3}: {}", i, instr.show());
             }
@@ -248,6 +258,15 @@ fn main() -> Fallible<()> {
         }
     }
 
+    if opt.trampolines {
+        let mut names = trampoline_usage.keys().cloned().collect::<Vec<_>>();
+        names.sort_by_key(|name| std::cmp::Reverse(trampoline_usage[name].len()));
+        for name in &names {
+            let shapes = &trampoline_usage[name];
+            println!("{:4} {:32}: {}", shapes.len(), name, shapes.join(", "));
+        }
+    }
+
     Ok(())
 }
 
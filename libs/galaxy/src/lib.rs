@@ -48,9 +48,7 @@ impl Galaxy {
             start_time: Instant::now(),
             _legion_universe: legion_universe,
             legion_world,
-            palette: Arc::new(Palette::from_bytes(
-                &catalog.read_name_sync("PALETTE.PAL")?,
-            )?),
+            palette: Arc::new(Palette::system(catalog)?),
             //lib,
         })
     }
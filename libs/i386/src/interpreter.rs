@@ -20,7 +20,10 @@ use crate::{
 };
 use failure::{bail, ensure, Fallible};
 use log::trace;
-use std::{collections::HashMap, mem};
+use std::{
+    collections::{HashMap, HashSet},
+    mem,
+};
 
 #[derive(Debug)]
 pub enum ExitInfo {
@@ -37,6 +40,48 @@ impl ExitInfo {
     }
 }
 
+// A named layout of the 32 bit fields of some game object that shape code reads through a
+// pointer to that object. Offsets are from the start of the object.
+#[derive(Clone, Debug, Default)]
+pub struct ObjectFieldMap {
+    fields: Vec<(String, u32, u32)>,
+}
+
+impl ObjectFieldMap {
+    pub fn new() -> Self {
+        Self { fields: Vec::new() }
+    }
+
+    pub fn with_field(mut self, name: &str, offset: u32, value: u32) -> Self {
+        self.fields.push((name.to_owned(), offset, value));
+        self
+    }
+
+    pub fn set(&mut self, name: &str, value: u32) -> Fallible<()> {
+        for field in self.fields.iter_mut() {
+            if field.0 == name {
+                field.2 = value;
+                return Ok(());
+            }
+        }
+        bail!("no object field named {}", name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<u32> {
+        self.fields
+            .iter()
+            .find(|(n, _, _)| n == name)
+            .map(|&(_, _, value)| value)
+    }
+
+    pub fn offset_of(&self, name: &str) -> Option<u32> {
+        self.fields
+            .iter()
+            .find(|(n, _, _)| n == name)
+            .map(|&(_, offset, _)| offset)
+    }
+}
+
 #[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
 enum MapProtection {
     // Read,
@@ -72,6 +117,8 @@ pub struct Interpreter {
     value_maps: HashMap<u32, u32>,
     bytecode: Vec<ByteCode>,
     trampolines: HashMap<u32, (String, usize)>,
+    draw_trampolines: HashSet<u32>,
+    draw_calls: Vec<(String, Vec<u32>)>,
 }
 
 impl Interpreter {
@@ -90,6 +137,8 @@ impl Interpreter {
             bytecode: Vec::new(),
             value_maps: HashMap::new(),
             trampolines: HashMap::new(),
+            draw_trampolines: HashSet::new(),
+            draw_calls: Vec::new(),
         }
     }
 
@@ -106,6 +155,22 @@ impl Interpreter {
         self.trampolines.insert(addr, (name.to_owned(), arg_count));
     }
 
+    // Register a trampoline into the engine's drawing code. Instead of stopping there, we log
+    // the call, drop its arguments, and carry on from the return address pushed below them,
+    // so that one run shows every draw in order.
+    pub fn add_draw_trampoline(&mut self, addr: u32, name: &str, arg_count: usize) {
+        self.add_trampoline(addr, name, arg_count);
+        self.draw_trampolines.insert(addr);
+    }
+
+    pub fn draw_call_log(&self) -> &[(String, Vec<u32>)] {
+        &self.draw_calls
+    }
+
+    pub fn clear_draw_call_log(&mut self) {
+        self.draw_calls.clear();
+    }
+
     pub fn map_value(&mut self, addr: u32, value: u32) {
         self.value_maps.insert(addr, value);
     }
@@ -114,6 +179,33 @@ impl Interpreter {
         self.value_maps.remove(&addr).unwrap()
     }
 
+    // Map every field of an object that lives at base.
+    pub fn bind_object(&mut self, base: u32, fields: &ObjectFieldMap) {
+        for &(_, offset, value) in &fields.fields {
+            self.map_value(base + offset, value);
+        }
+    }
+
+    // Unmap an object mapped with bind_object, updating the field values with anything that
+    // the code wrote to them. Fails if any of the fields is not mapped at base; the fields
+    // that are mapped are still unmapped and updated.
+    pub fn unbind_object(&mut self, base: u32, fields: &mut ObjectFieldMap) -> Fallible<()> {
+        let mut missing = Vec::new();
+        for field in fields.fields.iter_mut() {
+            match self.value_maps.remove(&(base + field.1)) {
+                Some(value) => field.2 = value,
+                None => missing.push(field.0.clone()),
+            }
+        }
+        ensure!(
+            missing.is_empty(),
+            "object fields not bound at {:08X}: {}",
+            base,
+            missing.join(", ")
+        );
+        Ok(())
+    }
+
     pub fn map_writable(&mut self, start: u32, data: Vec<u8>) -> Fallible<()> {
         ensure!(
             data.len() < u32::max_value() as usize,
@@ -234,7 +326,20 @@ impl Interpreter {
                         let (ref name, ref arg_count) = self.trampolines[&absolute];
                         let mut args = self.stack[self.stack.len() - *arg_count..].to_owned();
                         args.reverse();
-                        return Ok(ExitInfo::Trampoline(name.to_owned(), args));
+                        if !self.draw_trampolines.contains(&absolute) {
+                            return Ok(ExitInfo::Trampoline(name.to_owned(), args));
+                        }
+                        let arg_count = *arg_count;
+                        self.draw_calls.push((name.to_owned(), args));
+                        for _ in 0..arg_count {
+                            self.stack.pop();
+                            *self.esp_mut() += 4;
+                        }
+                        if self.stack.is_empty() {
+                            return Ok(ExitInfo::OutOfInstructions);
+                        }
+                        let resume = self.do_return()?;
+                        return self.interpret(resume);
                     }
                     return self.interpret(absolute);
                 }
@@ -794,4 +899,74 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn it_can_log_draw_calls() -> Fallible<()> {
+        let code = [
+            0x68, 0x10, 0x10, 0x00, 0x00, // push 0x1010
+            0x68, 0x07, 0x00, 0x00, 0x00, // push 7
+            0x68, 0x00, 0x20, 0x00, 0x00, // push 0x2000
+            0xC3, // ret
+            0x68, 0x20, 0x10, 0x00, 0x00, // push 0x1020
+            0x68, 0x09, 0x00, 0x00, 0x00, // push 9
+            0x68, 0x00, 0x20, 0x00, 0x00, // push 0x2000
+            0xC3, // ret
+            0x68, 0x00, 0x30, 0x00, 0x00, // push 0x3000
+            0xC3, // ret
+        ];
+        let mut interp = Interpreter::new();
+        for &start in &[0x00, 0x10, 0x20] {
+            let bc = ByteCode::disassemble_to_ret(0x1000 + start, &code[start..])?;
+            interp.add_code(bc);
+        }
+        interp.add_draw_trampoline(0x2000, "draw", 1);
+        interp.add_trampoline(0x3000, "finish", 0);
+
+        let (name, args) = interp.interpret(0x1000)?.ok_trampoline()?;
+        assert_eq!(name, "finish");
+        assert!(args.is_empty());
+        assert_eq!(
+            interp.draw_call_log(),
+            &[("draw".to_owned(), vec![7]), ("draw".to_owned(), vec![9])]
+        );
+        interp.clear_draw_call_log();
+        assert!(interp.draw_call_log().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn it_can_bind_an_object() -> Fallible<()> {
+        let code = [
+            0xBB, 0x00, 0x00, 0x06, 0x00, // mov ebx, 0x60000
+            0x8B, 0x43, 0x40, // mov eax, [ebx+0x40]
+            0x03, 0x43, 0x48, // add eax, [ebx+0x48]
+            0x50, // push eax
+            0x68, 0x00, 0x20, 0x00, 0x00, // push 0x2000
+            0xC3, // ret
+        ];
+        let bc = ByteCode::disassemble_to_ret(0x1000, &code)?;
+
+        let mut exp = ObjectFieldMap::new()
+            .with_field("field40", 0x40, 3)
+            .with_field("field48", 0x48, 4);
+        exp.set("field48", 5)?;
+        assert!(exp.set("field4C", 0).is_err());
+        assert_eq!(exp.offset_of("field48"), Some(0x48));
+
+        let mut interp = Interpreter::new();
+        interp.add_code(bc);
+        interp.add_trampoline(0x2000, "do_start_interp", 1);
+        interp.bind_object(0x60000, &exp);
+        let (name, args) = interp.interpret(0x1000)?.ok_trampoline()?;
+        assert_eq!(name, "do_start_interp");
+        assert_eq!(args, vec![8]);
+
+        interp.unbind_object(0x60000, &mut exp)?;
+        assert_eq!(exp.get("field40"), Some(3));
+
+        // Everything is already unbound, so there is nothing left to read back.
+        assert!(interp.unbind_object(0x60000, &mut exp).is_err());
+        assert_eq!(exp.get("field40"), Some(3));
+        Ok(())
+    }
 }
@@ -23,5 +23,5 @@ mod lut;
 
 pub use crate::{
     disassembler::{ByteCode, DisassemblyError, Instr, MemRef, Memonic, Operand, Reg},
-    interpreter::{ExitInfo, Interpreter},
+    interpreter::{ExitInfo, Interpreter, ObjectFieldMap},
 };
@@ -14,9 +14,9 @@
 // along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
 use crate::{LibDrawer, Priority, GAME_INFO};
 use catalog::{Catalog, DirectoryDrawer, FileId};
-use failure::Fallible;
+use failure::{bail, Fallible};
 use glob::{MatchOptions, Pattern};
-use std::{env, fs, path::PathBuf};
+use std::{borrow::Cow, collections::HashMap, env, fs, path::PathBuf};
 
 // FA Engine aware lookup of asset files. This can run in several modes:
 //   1) Collect all games as tags under test_data/packed and add lib drawers
@@ -24,6 +24,40 @@ use std::{env, fs, path::PathBuf};
 //   3) Collect all files under a single tag in the current directory.
 pub struct CatalogBuilder;
 
+// Every game we can find, mounted together under their per-game labels.
+pub struct Libs {
+    catalog: Catalog,
+}
+
+impl Libs {
+    pub fn all() -> Fallible<Self> {
+        Ok(Self {
+            catalog: CatalogBuilder::build()?,
+        })
+    }
+
+    pub fn catalog(&self) -> &Catalog {
+        &self.catalog
+    }
+
+    // Report which games carry a file with the given name. The same asset name frequently
+    // exists in several games, but not all of them: e.g. KURILE.T2 ships with USNF97, but is
+    // missing from ATFGOLD, even though ATFGOLD's MM files reference it.
+    pub fn games_containing(&self, name: &str) -> Vec<&'static str> {
+        let pattern = Pattern::escape(&name.to_uppercase());
+        GAME_INFO
+            .iter()
+            .filter(|game| {
+                self.catalog
+                    .find_labeled_matching(&game.label(), &pattern)
+                    .map(|matching| !matching.is_empty())
+                    .unwrap_or(false)
+            })
+            .map(|game| game.test_dir)
+            .collect()
+    }
+}
+
 impl CatalogBuilder {
     pub fn build() -> Fallible<Catalog> {
         let mut catalog = Catalog::empty();
@@ -138,6 +172,47 @@ impl CatalogBuilder {
         Ok((catalog, selected))
     }
 
+    // Group every file in the given game by its uppercase extension, e.g. SH or PIC, in a
+    // single pass over the catalog. Files without an extension are filed under "".
+    pub fn index_by_extension(
+        catalog: &Catalog,
+        label: &str,
+    ) -> Fallible<HashMap<String, Vec<FileId>>> {
+        let mut index = HashMap::new();
+        for fid in catalog.find_labeled_matching(label, "*")? {
+            let name = catalog.stat_sync(fid)?.name;
+            let ext = match name.rfind('.') {
+                Some(dot) => name[dot + 1..].to_uppercase(),
+                None => String::new(),
+            };
+            index.entry(ext).or_insert_with(Vec::new).push(fid);
+        }
+        Ok(index)
+    }
+
+    // MM and M files refer to assets with whatever case and sigil the author happened to
+    // type. Find the name that the catalog actually uses: uppercase, then with the $ and ~
+    // sigils swapped, then without any sigil at all.
+    pub fn find_name_ci(catalog: &Catalog, name: &str) -> Option<String> {
+        let raw = name.to_uppercase();
+        let mut candidates = vec![raw.clone()];
+        if raw.starts_with('$') || raw.starts_with('~') {
+            let sigil = if raw.starts_with('$') { "~" } else { "$" };
+            candidates.push(sigil.to_owned() + &raw[1..]);
+            candidates.push(raw[1..].to_owned());
+        }
+        candidates
+            .into_iter()
+            .find(|candidate| catalog.exists(candidate))
+    }
+
+    pub fn read_name_ci<'a>(catalog: &'a Catalog, name: &str) -> Fallible<Cow<'a, [u8]>> {
+        match Self::find_name_ci(catalog, name) {
+            Some(found) => catalog.read_name_sync(&found),
+            None => bail!("no file matching {} found", name),
+        }
+    }
+
     fn find_test_data_dir(mut cwd: PathBuf) -> Option<PathBuf> {
         loop {
             if cwd.join("test_data").exists() {
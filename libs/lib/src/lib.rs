@@ -19,7 +19,7 @@
 mod catalog_builder;
 mod game_info;
 pub use crate::{
-    catalog_builder::CatalogBuilder,
+    catalog_builder::{CatalogBuilder, Libs},
     game_info::{GameInfo, GAME_INFO},
 };
 
@@ -330,4 +330,49 @@ mod tests {
         let _catalog = CatalogBuilder::build()?;
         Ok(())
     }
+
+    #[test]
+    fn test_games_containing() -> Fallible<()> {
+        let libs = Libs::all()?;
+        // The numbered Ukraine maps were only shipped with the original USNF and its expansion.
+        let games = libs.games_containing("$UKR1.T2");
+        assert!(!games.is_empty());
+        for game in &games {
+            assert!(["USNF", "MF"].contains(game));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_by_extension() -> Fallible<()> {
+        let catalog = CatalogBuilder::build()?;
+        let fa = GAME_INFO.iter().find(|game| game.test_dir == "FA").unwrap();
+        let label = fa.label();
+        let index = CatalogBuilder::index_by_extension(&catalog, &label)?;
+        for ext in &["SH", "M", "MM", "T2", "PIC"] {
+            let expect = catalog.find_labeled_matching(&label, &format!("*.{}", ext))?;
+            assert!(!expect.is_empty());
+            assert_eq!(index[*ext].len(), expect.len());
+        }
+        let total = index.values().map(|fids| fids.len()).sum::<usize>();
+        assert_eq!(total, catalog.find_labeled_matching(&label, "*")?.len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_name_ci() -> Fallible<()> {
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["FA:EGY3.PIC".to_owned()])?;
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let expect = catalog.read_sync(fid)?.to_vec();
+            for name in &["egy3.pic", "EGY3.PIC", "$egy3.PIC"] {
+                let found = CatalogBuilder::find_name_ci(&catalog, name);
+                assert_eq!(found.as_deref(), Some("EGY3.PIC"));
+                assert_eq!(CatalogBuilder::read_name_ci(&catalog, name)?, expect);
+            }
+            assert!(CatalogBuilder::find_name_ci(&catalog, "~nope.PIC").is_none());
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,57 @@
+// This file is part of OpenFA.
+//
+// OpenFA is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// OpenFA is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
+use crate::MissionMap;
+use catalog::Catalog;
+use failure::Fallible;
+use lib::from_dos_string;
+
+impl MissionMap {
+    // The text shown on the brief and briefmap screens. We do not parse M files yet, so we
+    // cannot follow the mission's own reference; instead, look for the MT file that sits
+    // next to the mission, e.g. U01.MT for U01.M. Missions without one have no briefing.
+    pub fn briefing_text(mission_name: &str, catalog: &Catalog) -> Fallible<Option<String>> {
+        let stem = match mission_name.rfind('.') {
+            Some(dot) => &mission_name[..dot],
+            None => mission_name,
+        };
+        let name = format!("{}.MT", stem.to_uppercase());
+        if !catalog.exists(&name) {
+            return Ok(None);
+        }
+        let text = from_dos_string(catalog.read_name_sync(&name)?);
+        Ok(Some(text.trim_end_matches('\0').to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use lib::CatalogBuilder;
+
+    #[test]
+    fn it_can_find_briefings() -> Fallible<()> {
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["FA:*.MT".to_owned()])?;
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let name = catalog.stat_sync(fid)?.name;
+            let mission = name.replace(".MT", ".M").to_lowercase();
+            let text = MissionMap::briefing_text(&mission, &catalog)?;
+            assert!(!text.expect("a briefing").trim().is_empty());
+        }
+        assert!(MissionMap::briefing_text("NOSUCH.M", &catalog)?.is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,52 @@
+// This file is part of OpenFA.
+//
+// OpenFA is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// OpenFA is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
+use catalog::Catalog;
+use failure::Fallible;
+use sh::{build_mesh, MeshData, MeshOptions, RawShape};
+use std::ops::AddAssign;
+
+// How much geometry something will send to the renderer.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct GeometryBudget {
+    pub vertices: usize,
+    pub triangles: usize,
+}
+
+impl GeometryBudget {
+    // Polygons are counted as the number of triangles in a fan over them.
+    pub fn for_mesh(mesh: &MeshData) -> Self {
+        Self {
+            vertices: mesh.positions.len(),
+            triangles: mesh
+                .faces
+                .iter()
+                .map(|face| face.indices.len().saturating_sub(2))
+                .sum(),
+        }
+    }
+
+    // The budget of the highest detail model of the named shape.
+    pub fn for_shape(name: &str, catalog: &Catalog) -> Fallible<Self> {
+        let sh = RawShape::from_bytes(&catalog.read_name_sync(name)?)?;
+        Ok(Self::for_mesh(&build_mesh(&sh, &MeshOptions::default())?))
+    }
+}
+
+impl AddAssign for GeometryBudget {
+    fn add_assign(&mut self, other: Self) {
+        self.vertices += other.vertices;
+        self.triangles += other.triangles;
+    }
+}
@@ -0,0 +1,205 @@
+// This file is part of OpenFA.
+//
+// OpenFA is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// OpenFA is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
+use crate::MissionMap;
+use catalog::Catalog;
+use failure::{ensure, Fallible};
+use lib::from_dos_string;
+
+// A map reference as it appears after the map key, e.g. $egy3.T2 or ~ukrf.T2. See the
+// notes on find_t2_for_map for all the forms that these take.
+//
+// Only decimal digits count as the map's number. It is tempting to read a trailing F as hex
+// 15, since the freeform maps sort after the numbered ones, but then ~KURILE would come out
+// as KURIL number 14 (E), so F is only taken as freeform after a three letter terrain.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MapName {
+    prefix: Option<char>,
+    base: String,
+    number: Option<u32>,
+    freeform: bool,
+}
+
+impl MapName {
+    pub fn parse(raw: &str) -> Fallible<Self> {
+        let stem = raw.split('.').next().unwrap_or(raw).to_uppercase();
+        ensure!(!stem.is_empty(), "empty map name");
+        let prefix = stem.chars().next().filter(|&c| c == '$' || c == '~');
+        let stem = &stem[prefix.map(char::len_utf8).unwrap_or(0)..];
+        let digits = stem.chars().rev().take_while(char::is_ascii_digit).count();
+        let (base, number) = stem.split_at(stem.len() - digits);
+        ensure!(!base.is_empty(), "map name {} has no base", raw);
+        let freeform =
+            prefix.is_some() && number.is_empty() && base.len() == 4 && base.ends_with('F');
+        let base = if freeform { &base[..3] } else { base };
+        Ok(Self {
+            prefix,
+            base: base.to_owned(),
+            number: if number.is_empty() {
+                None
+            } else {
+                Some(number.parse::<u32>()?)
+            },
+            freeform,
+        })
+    }
+
+    // The $ or ~ in front of the name, if any.
+    pub fn prefix(&self) -> Option<char> {
+        self.prefix
+    }
+
+    // The terrain the map is derived from, uppercase, e.g. EGY.
+    pub fn base(&self) -> &str {
+        &self.base
+    }
+
+    // The position of the map in its campaign, if it is one of the numbered maps.
+    pub fn number(&self) -> Option<u32> {
+        self.number
+    }
+
+    // One of the ~ maps for free flight, e.g. ~egyf.T2.
+    pub fn is_freeform(&self) -> bool {
+        self.freeform
+    }
+}
+
+// The missions that are built on the $-prefixed, numbered variants of one terrain, e.g.
+// $egy1.T2 through $egy9.T2, in order.
+#[derive(Clone, Debug)]
+pub struct Campaign {
+    prefix: String,
+    missions: Vec<(u32, String)>,
+}
+
+impl Campaign {
+    // Scan every MM in the catalog for the map it is built on.
+    pub fn from_catalog(prefix: &str, catalog: &Catalog) -> Fallible<Self> {
+        let mut maps = Vec::new();
+        for name in catalog.find_matching_names("*.MM")?.iter() {
+            let name = name.to_string();
+            let contents = from_dos_string(catalog.read_name_sync(&name)?);
+            maps.push((name, MissionMap::peek_map_name(&contents)?));
+        }
+        Self::from_map_names(prefix, &maps)
+    }
+
+    // Build a campaign from (mission name, map name) pairs.
+    pub fn from_map_names(prefix: &str, maps: &[(String, MapName)]) -> Fallible<Self> {
+        let prefix = prefix.to_uppercase();
+        let mut missions = Vec::new();
+        for (mission, map) in maps {
+            if map.prefix() != Some('$') || map.base() != prefix {
+                continue;
+            }
+            if let Some(number) = map.number() {
+                missions.push((number, mission.to_owned()));
+            }
+        }
+        missions.sort();
+        Ok(Self { prefix, missions })
+    }
+
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    // Every mission in campaign order, with the number of the map it is on. Several missions
+    // may share a map.
+    pub fn missions(&self) -> &[(u32, String)] {
+        &self.missions
+    }
+
+    // The map numbers between the first and last that no mission uses.
+    pub fn gaps(&self) -> Vec<u32> {
+        let (first, last) = match (self.missions.first(), self.missions.last()) {
+            (Some(first), Some(last)) => (first.0, last.0),
+            _ => return Vec::new(),
+        };
+        (first..=last)
+            .filter(|n| !self.missions.iter().any(|(number, _)| number == n))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use lib::CatalogBuilder;
+
+    #[test]
+    fn it_can_parse_map_names() -> Fallible<()> {
+        let name = MapName::parse("$egy3.T2")?;
+        assert_eq!(name.prefix(), Some('$'));
+        assert_eq!(name.base(), "EGY");
+        assert_eq!(name.number(), Some(3));
+        assert!(!name.is_freeform());
+        let name = MapName::parse("~ukrf.T2")?;
+        assert_eq!(name.prefix(), Some('~'));
+        assert_eq!(name.base(), "UKR");
+        assert_eq!(name.number(), None);
+        assert!(name.is_freeform());
+        let name = MapName::parse("egy.T2")?;
+        assert_eq!((name.prefix(), name.base()), (None, "EGY"));
+        assert!(!name.is_freeform());
+        assert_eq!(MapName::parse("kurile.T2")?.prefix(), None);
+        let name = MapName::parse("~kurile.T2")?;
+        assert_eq!((name.base(), name.number()), ("KURILE", None));
+        assert!(!name.is_freeform());
+        assert_eq!(MapName::parse("~tviet.T2")?.base(), "TVIET");
+        Ok(())
+    }
+
+    #[test]
+    fn it_reports_campaign_gaps() -> Fallible<()> {
+        let maps = [
+            ("B.MM", "$egy3.T2"),
+            ("A.MM", "$egy1.T2"),
+            ("C.MM", "~egy2.T2"),
+        ]
+        .iter()
+        .map(|(mission, map)| Ok((mission.to_string(), MapName::parse(map)?)))
+        .collect::<Fallible<Vec<_>>>()?;
+        let campaign = Campaign::from_map_names("egy", &maps)?;
+        assert_eq!(
+            campaign.missions(),
+            &[(1, "A.MM".to_owned()), (3, "B.MM".to_owned())]
+        );
+        assert_eq!(campaign.gaps(), vec![2]);
+        Ok(())
+    }
+
+    #[test]
+    fn it_can_order_a_campaign() -> Fallible<()> {
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["FA:BAL.MM".to_owned()])?;
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let campaign = Campaign::from_catalog("egy", &catalog)?;
+            let numbers = campaign
+                .missions()
+                .iter()
+                .map(|(number, _)| *number)
+                .collect::<Vec<_>>();
+            let mut sorted = numbers.clone();
+            sorted.sort();
+            assert_eq!(numbers, sorted);
+            assert_eq!(numbers.first(), Some(&1));
+            assert_eq!(numbers.last(), Some(&9));
+            assert!(campaign.gaps().is_empty());
+        }
+        Ok(())
+    }
+}
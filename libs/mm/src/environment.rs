@@ -0,0 +1,50 @@
+// This file is part of OpenFA.
+//
+// OpenFA is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// OpenFA is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
+use nalgebra::Vector3;
+use std::{f32::consts::PI, time::Duration};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+// What the sky looks like at some point in a mission. Missions do not schedule any weather
+// changes that we know of, so only the sun moves.
+#[derive(Clone, Debug)]
+pub struct Environment {
+    // Seconds since midnight, wrapped to a single day.
+    pub seconds_of_day: u64,
+
+    // Unit vector pointing at the sun in map space: x east, y up, z north. The sun rises
+    // in the east at 06:00 and sets in the west at 18:00, and is below the horizon at night.
+    pub sun_direction: Vector3<f32>,
+
+    pub wind: (i16, i16),
+}
+
+impl Environment {
+    pub(crate) fn new(start: (u8, u8), elapsed: Duration, wind: (i16, i16)) -> Self {
+        let start_seconds = u64::from(start.0) * 3600 + u64::from(start.1) * 60;
+        let seconds_of_day = (start_seconds + elapsed.as_secs()) % SECONDS_PER_DAY;
+
+        // Angle of the sun above the eastern horizon; 0 at dawn, PI at dusk.
+        let hours = seconds_of_day as f32 / 3600f32;
+        let angle = (hours - 6f32) / 12f32 * PI;
+        let sun_direction = Vector3::new(angle.cos(), angle.sin(), 0f32);
+
+        Self {
+            seconds_of_day,
+            sun_direction,
+            wind,
+        }
+    }
+}
@@ -0,0 +1,124 @@
+// This file is part of OpenFA.
+//
+// OpenFA is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// OpenFA is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
+use crate::MissionMap;
+use catalog::Catalog;
+use failure::Fallible;
+use pal::Palette;
+use sh::{
+    build_mesh, CoordinateConvention, FacetFlags, MeshData, MeshFace, MeshOptions, RawShape,
+    SceneBuilder,
+};
+use std::collections::HashMap;
+use t2::{Terrain, TerrainMesh};
+
+// Untextured faces, one per terrain quad.
+fn to_mesh_data(mesh: &TerrainMesh) -> MeshData {
+    let faces = mesh
+        .quads
+        .iter()
+        .map(|quad| {
+            let mut face = MeshFace {
+                indices: quad.indices.to_vec(),
+                tex_coords: Vec::new(),
+                texture: None,
+                color: quad.color,
+                flags: FacetFlags::empty(),
+                normal: [0f32; 3],
+                material_normal: None,
+            };
+            face.normal = face.compute_normal(&mesh.positions);
+            face
+        })
+        .collect();
+    MeshData::new(mesh.positions.clone(), faces)
+}
+
+impl MissionMap {
+    // Export the terrain and every object as one glTF scene. Everything is in feet, with
+    // x east, y up, and z north from the corner of the map, as in the mission file. The
+    // terrain is always the first node; objects without a shape get an empty node so that
+    // there is still one node per object. Everything is colored with the given palette, so
+    // the terrain will not match what the game draws.
+    pub fn to_gltf_scene(&self, catalog: &Catalog, palette: &Palette) -> Fallible<gltf_json::Root> {
+        let mut scene = SceneBuilder::new();
+
+        let terrain = Terrain::from_bytes(&catalog.read_name_sync(self.t2_name())?)?;
+        let terrain_mesh =
+            scene.add_mesh(terrain.name(), &to_mesh_data(&terrain.to_mesh()), palette)?;
+        scene.add_node(
+            self.t2_name(),
+            Some(terrain_mesh),
+            [0f32; 3],
+            [0f32, 0f32, 0f32, 1f32],
+        );
+
+        let options = MeshOptions {
+            convention: CoordinateConvention::YUp,
+            ..Default::default()
+        };
+        let mut meshes = HashMap::new();
+        for info in self.objects() {
+            let xt = info.xt();
+            let mesh = match xt.ot().shape {
+                Some(ref name) => {
+                    if !meshes.contains_key(name) {
+                        let sh = RawShape::from_bytes(&catalog.read_name_sync(name)?)?;
+                        let mesh = build_mesh(&sh, &options)?;
+                        meshes.insert(name.to_owned(), scene.add_mesh(name, &mesh, palette)?);
+                    }
+                    Some(meshes[name])
+                }
+                None => None,
+            };
+            let name = info.name().unwrap_or_else(|| info.type_name().to_owned());
+            let p = info.position();
+            let q = info.angle().as_ref().coords;
+            scene.add_node(&name, mesh, [p.x, p.y, p.z], [q[0], q[1], q[2], q[3]]);
+        }
+
+        Ok(scene.finish())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use lib::{from_dos_string, CatalogBuilder};
+    use xt::TypeManager;
+
+    #[test]
+    fn it_can_export_a_mission_scene() -> Fallible<()> {
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["FA:BAL.MM".to_owned()])?;
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let type_manager = TypeManager::empty();
+            let contents = from_dos_string(catalog.read_sync(fid)?);
+            let mm = MissionMap::from_str(&contents, &type_manager, &catalog)?;
+            let palette = Palette::system(&catalog)?;
+            let root = mm.to_gltf_scene(&catalog, &palette)?;
+
+            assert_eq!(root.nodes.len(), mm.objects().len() + 1);
+            assert_eq!(root.scenes[0].nodes.len(), root.nodes.len());
+            assert_eq!(root.nodes[0].translation, Some([0f32; 3]));
+            for (node, info) in root.nodes[1..].iter().zip(mm.objects()) {
+                let p = info.position();
+                assert_eq!(node.translation, Some([p.x, p.y, p.z]));
+                assert_eq!(node.mesh.is_some(), info.xt().ot().shape.is_some());
+            }
+        }
+        Ok(())
+    }
+}
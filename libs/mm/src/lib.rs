@@ -14,15 +14,43 @@
 // along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
 #![allow(clippy::cognitive_complexity)]
 
+mod briefing;
+mod budget;
+mod campaign;
+mod environment;
+mod gltf;
 mod obj;
+mod quick;
+mod scene;
 mod special;
+mod token;
 mod util;
 mod waypoint;
 
-use crate::{obj::ObjectInfo, special::SpecialInfo, waypoint::Waypoint};
-use catalog::Catalog;
+pub use crate::{
+    budget::GeometryBudget,
+    campaign::{Campaign, MapName},
+    environment::Environment,
+    obj::Nationality,
+    quick::QuickMission,
+    scene::{MissionScene, SceneObject, SceneTile},
+    token::{MValue, MValueStream},
+    waypoint::Waypoint,
+};
+
+use crate::{obj::ObjectInfo, special::SpecialInfo, util::maybe_hex};
+use catalog::{Catalog, FileId};
 use failure::{bail, ensure, err_msg, Fallible};
-use std::{collections::HashMap, str::FromStr};
+use fnv::FnvHasher;
+use lib::CatalogBuilder;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    hash::{Hash, Hasher},
+    str::FromStr,
+    time::Duration,
+};
+use t2::Terrain;
 use xt::TypeManager;
 
 #[derive(Clone, Debug, Eq, PartialEq, Hash)]
@@ -45,9 +73,31 @@ impl TLoc {
             Self::Name(_) => true,
         }
     }
+
+    // Find the PIC for this tile in the catalog's default game. Numbered tiles are written
+    // both with and without a leading zero, depending on the game, so try both; names get
+    // the usual case and sigil fixups from find_name_ci.
+    pub fn resolve(&self, base: &str, catalog: &Catalog) -> Fallible<FileId> {
+        let mut candidates = vec![self.pic_file(base)];
+        if let TLoc::Index(i) = self {
+            let padded = format!("{}{:02}.PIC", base, i);
+            if !candidates.contains(&padded) {
+                candidates.push(padded);
+            }
+        }
+        for candidate in &candidates {
+            if let Some(name) = CatalogBuilder::find_name_ci(catalog, candidate) {
+                let found = catalog.find_labeled_matching(catalog.default_label(), &name)?;
+                if let Some(&fid) = found.first() {
+                    return Ok(fid);
+                }
+            }
+        }
+        bail!("no PIC for tile; tried: {}", candidates.join(", "))
+    }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum MapOrientation {
     Unk0,
     Unk1,
@@ -65,32 +115,93 @@ impl MapOrientation {
             _ => bail!("invalid orientation"),
         })
     }
+
+    pub fn as_byte(self) -> u8 {
+        match self {
+            MapOrientation::Unk0 => 0,
+            MapOrientation::Unk1 => 1,
+            MapOrientation::FlipS => 2,
+            MapOrientation::RotateCCW => 3,
+        }
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct TMap {
     pub orientation: MapOrientation,
     pub loc: TLoc,
 }
 
+impl TMap {
+    // Format this tile as the MM line that would place it at x, y. Named tiles only carry
+    // an orientation if they need one, matching what the shipped maps do.
+    pub fn to_line(&self, x: u32, y: u32) -> String {
+        match &self.loc {
+            TLoc::Index(i) => format!("tmap {} {} {} {}", x, y, i, self.orientation.as_byte()),
+            TLoc::Name(name) => {
+                let stem = name.trim_end_matches(".PIC").to_lowercase();
+                if self.orientation == MapOrientation::Unk0 {
+                    format!("tmap_named {} {} {}", stem, x, y)
+                } else {
+                    format!(
+                        "tmap_named {} {} {} {}",
+                        stem,
+                        x,
+                        y,
+                        self.orientation.as_byte()
+                    )
+                }
+            }
+        }
+    }
+}
+
+// Problems with how a mission lays tiles over its terrain.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum LayoutIssue {
+    // A tmap that points past the end of the tdic list.
+    MissingTDic { x: u32, y: u32, index: usize },
+    // A tmap or tmap_named that is not over the T2.
+    OutOfBounds { x: u32, y: u32 },
+    // More than one tmap at the same spot; all but the last are ignored.
+    Duplicate { x: u32, y: u32 },
+}
+
+// Problems that from_str reads past, so that the rest of the mission is still usable, but
+// that from_str_strict refuses.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MmWarning {
+    // A tmap that points past the end of the tdic list.
+    MissingTDic { x: u32, y: u32, index: usize },
+}
+
 #[allow(dead_code)]
+#[derive(Debug, Hash, PartialEq)]
 pub struct TDic {
     n: usize,
     map: [[u8; 4]; 8],
 }
 
 #[allow(dead_code)]
+#[derive(Debug, PartialEq)]
 pub struct MissionMap {
     map_name: String,
     t2_name: String,
     layer_name: String,
     layer_index: usize,
     tmaps: HashMap<(u32, u32), TMap>,
+    // Coordinates that had more than one tmap; only the last survives in tmaps.
+    duplicate_tmaps: Vec<(u32, u32)>,
     tdics: Vec<TDic>,
+    clouds: u32,
     wind: (i16, i16),
     view: (u32, u32, u32),
     time: (u8, u8),
+    sides: Option<Vec<u8>>,
+    sides_version: Option<u8>,
     objects: Vec<ObjectInfo>,
+    specials: Vec<SpecialInfo>,
+    warnings: Vec<MmWarning>,
 }
 
 impl MissionMap {
@@ -102,13 +213,17 @@ impl MissionMap {
         let mut t2_name = None;
         let mut layer_name = None;
         let mut layer_index = None;
+        let mut clouds = 0;
         let mut wind = Some((0, 0));
         let mut view = None;
         let mut time = None;
         let mut sides = Vec::new();
+        let mut sides_version = None;
         let mut objects = Vec::new();
+        let mut success_flags = None;
         let mut specials = Vec::new();
         let mut tmaps = HashMap::new();
+        let mut duplicate_tmaps = Vec::new();
         let mut tdics = Vec::new();
 
         let mut offset = 1;
@@ -122,6 +237,11 @@ impl MissionMap {
             if parts.is_empty() {
                 continue;
             }
+            ensure!(
+                success_flags.is_none() || parts[0] == "obj" || parts[0].trim().is_empty(),
+                "map_obj_success_flags must be followed by an obj, not {}",
+                parts[0]
+            );
 
             match parts[0] {
                 "map" => {
@@ -138,7 +258,7 @@ impl MissionMap {
                     layer_index = Some(parts[2].parse::<usize>()?);
                 }
                 "clouds" => {
-                    ensure!(parts[1] == "0", "expected 0 clouds value");
+                    clouds = parts[1].trim().parse::<u32>()?;
                 }
                 "wind" => {
                     // The air is perfectly still in Ukraine.
@@ -160,8 +280,9 @@ impl MissionMap {
                     time = Some((h, m));
                 }
                 "sides" => {
-                    // Only used by Ukraine.
+                    // Only used by Ukraine; other maps may not have any sides at all.
                     assert!(sides.is_empty());
+                    sides_version = Some(1);
                     loop {
                         let next_offset = offset + 1;
                         if let Ok(side) = str::parse::<u8>(lines[next_offset].trim()) {
@@ -177,6 +298,7 @@ impl MissionMap {
                 "sides2" => {
                     // Same as `sides`, but with hex values. Same 0 or 128 assertion.
                     assert!(sides.is_empty());
+                    sides_version = Some(2);
                     loop {
                         let next_offset = offset + 1;
                         let trimmed = lines[next_offset].trim();
@@ -193,6 +315,7 @@ impl MissionMap {
                 "sides3" => {
                     // Same as `sides2`.
                     assert!(sides.is_empty());
+                    sides_version = Some(3);
                     loop {
                         let next_offset = offset + 1;
                         let trimmed = lines[next_offset].trim();
@@ -209,6 +332,7 @@ impl MissionMap {
                 "sides4" => {
                     // Same as `sides2`.
                     assert!(sides.is_empty());
+                    sides_version = Some(4);
                     loop {
                         let next_offset = offset + 1;
                         let trimmed = lines[next_offset].trim();
@@ -226,9 +350,20 @@ impl MissionMap {
                     let historical_era = u8::from_str(parts[1])?;
                     assert_eq!(historical_era, 4);
                 }
+                "map_obj_success_flags" => {
+                    // This belongs to the obj that follows it.
+                    ensure!(success_flags.is_none(), "repeated map_obj_success_flags");
+                    let a = parts[1].parse::<i32>()?;
+                    let b = maybe_hex::<u8>(parts[2].trim())?;
+                    success_flags = Some((a, b));
+                }
                 "obj" => {
                     offset += 1;
-                    let obj = ObjectInfo::from_lines(&lines, &mut offset, type_manager, catalog)?;
+                    let mut obj =
+                        ObjectInfo::from_lines(&lines, &mut offset, type_manager, catalog)?;
+                    if let Some(flags) = success_flags.take() {
+                        obj.set_success_flags(flags);
+                    }
                     objects.push(obj);
                 }
                 "special" => {
@@ -237,11 +372,9 @@ impl MissionMap {
                     specials.push(special);
                 }
                 "tmap" => {
-                    let x = parts[1].parse::<i16>()? as u32;
-                    let y = parts[2].parse::<i16>()? as u32;
-                    ensure!(x % 4 == 0, "unaligned tmap x index");
-                    ensure!(y % 4 == 0, "unaligned tmap y index");
-                    tmaps.insert(
+                    let x = Self::parse_tile_coord(parts[1], "tmap x index")?;
+                    let y = Self::parse_tile_coord(parts[2], "tmap y index")?;
+                    let prior = tmaps.insert(
                         (x, y),
                         TMap {
                             orientation: MapOrientation::from_byte(
@@ -250,19 +383,29 @@ impl MissionMap {
                             loc: TLoc::Index(parts[3].parse::<usize>()?),
                         },
                     );
+                    if prior.is_some() {
+                        duplicate_tmaps.push((x, y));
+                    }
                 }
                 "tmap_named" => {
-                    let x = parts[2].parse::<i16>()? as u32;
-                    let y = parts[3].parse::<i16>()? as u32;
-                    ensure!(x % 4 == 0, "unaligned tmap_named x index");
-                    ensure!(y % 4 == 0, "unaligned tmap_named y index");
-                    tmaps.insert(
+                    let x = Self::parse_tile_coord(parts[2], "tmap_named x index")?;
+                    let y = Self::parse_tile_coord(parts[3], "tmap_named y index")?;
+                    // Most named tiles stop after the coordinates, but some carry an
+                    // orientation in the same position as a normal tmap.
+                    let orientation = match parts.get(4).map(|s| s.trim()) {
+                        Some(s) if !s.is_empty() => s.parse::<u8>()?,
+                        _ => 0,
+                    };
+                    let prior = tmaps.insert(
                         (x, y),
                         TMap {
-                            orientation: MapOrientation::from_byte(0)?,
+                            orientation: MapOrientation::from_byte(orientation)?,
                             loc: TLoc::Name(format!("{}.PIC", parts[1].to_uppercase())),
                         },
                     );
+                    if prior.is_some() {
+                        duplicate_tmaps.push((x, y));
+                    }
                 }
                 "tdic" => {
                     offset += 1;
@@ -334,29 +477,91 @@ impl MissionMap {
             offset += 1;
         }
 
-        for tmap in tmaps.iter() {
-            if let TLoc::Index(i) = tmap.1.loc {
-                ensure!(
-                    (i as usize) < tdics.len(),
-                    "expected at tdict for each tmap index"
-                );
+        ensure!(
+            success_flags.is_none(),
+            "map_obj_success_flags at the end of the file"
+        );
+
+        let mut warnings = Vec::new();
+        for (&(x, y), tmap) in tmaps.iter() {
+            if let TLoc::Index(index) = tmap.loc {
+                if index >= tdics.len() {
+                    warnings.push(MmWarning::MissingTDic { x, y, index });
+                }
             }
         }
+        warnings.sort_by_key(|warning| match *warning {
+            MmWarning::MissingTDic { x, y, .. } => (y, x),
+        });
 
+        ensure!(
+            sides.len() <= 64,
+            "mm: {} sides, but there are only 64 nationalities",
+            sides.len()
+        );
         Ok(MissionMap {
             map_name: map_name.ok_or_else(|| err_msg("mm must have a 'map' key"))?,
             t2_name: t2_name.ok_or_else(|| err_msg("mm must have a 'map' key"))?,
             layer_name: layer_name.ok_or_else(|| err_msg("mm must have a 'layer' key"))?,
             layer_index: layer_index.ok_or_else(|| err_msg("mm must have a 'layer' key"))?,
+            clouds,
             wind: wind.ok_or_else(|| err_msg("mm must have a 'wind' key"))?,
             view: view.ok_or_else(|| err_msg("mm must have a 'view' key"))?,
             time: time.ok_or_else(|| err_msg("mm must have a 'time' key"))?,
             tmaps,
+            duplicate_tmaps,
             tdics,
+            sides: sides_version.map(|_| sides),
+            sides_version,
             objects,
+            specials,
+            warnings,
         })
     }
 
+    // As from_str, but fail on anything that from_str would only warn about.
+    pub fn from_str_strict(
+        s: &str,
+        type_manager: &TypeManager,
+        catalog: &Catalog,
+    ) -> Fallible<Self> {
+        let mm = Self::from_str(s, type_manager, catalog)?;
+        if let Some(warning) = mm.warnings.first() {
+            bail!("invalid mission map: {:?}", warning);
+        }
+        Ok(mm)
+    }
+
+    // Find the map, e.g. ~ukr1.T2, that an MM or M file is built on, without parsing the
+    // rest of the file or touching the catalog.
+    pub fn peek_map_name(s: &str) -> Fallible<MapName> {
+        let mut stream = MValue::stream(s);
+        while let Some(value) = stream.next() {
+            if value? == MValue::Word("map".to_owned()) {
+                return match stream.next() {
+                    Some(Ok(MValue::Word(name))) => MapName::parse(&name),
+                    Some(Err(e)) => Err(e),
+                    _ => bail!("expected a map name after the map key"),
+                };
+            }
+        }
+        bail!("mission must have a 'map' key")
+    }
+
+    // Tiles are placed by T2 sample and every T2 starts at 0, so a negative coordinate can
+    // only be a mistake. Reject it instead of letting it wrap around to a huge index.
+    fn parse_tile_coord(s: &str, what: &str) -> Fallible<u32> {
+        let v = s.parse::<i16>()?;
+        ensure!(v >= 0, "negative {}: {}", what, v);
+        ensure!(v % 4 == 0, "unaligned {}", what);
+        Ok(v as u32)
+    }
+
+    // Everything that from_str read past, sorted by position.
+    pub fn warnings(&self) -> &[MmWarning] {
+        &self.warnings
+    }
+
     pub fn t2_name(&self) -> &str {
         &self.t2_name
     }
@@ -377,10 +582,121 @@ impl MissionMap {
         self.tmaps.get(&(xi, zi))
     }
 
+    // The editor's default camera position over the map. M files carry their own view,
+    // which may differ, but we cannot parse M files yet, so only the map's is available.
+    pub fn view(&self) -> (u32, u32, u32) {
+        self.view
+    }
+
+    // Check the tiles against the terrain they are laid over. Issues are sorted by
+    // coordinate so that reports are stable. Missing tdics are also in warnings, but we
+    // check again so that the report is complete on its own.
+    pub fn validate_layout(&self, terrain: &Terrain) -> Vec<LayoutIssue> {
+        let mut issues = Vec::new();
+        for (&(x, y), tmap) in &self.tmaps {
+            if let TLoc::Index(index) = tmap.loc {
+                if index >= self.tdics.len() {
+                    issues.push(LayoutIssue::MissingTDic { x, y, index });
+                }
+            }
+            if x >= terrain.width() || y >= terrain.height() {
+                issues.push(LayoutIssue::OutOfBounds { x, y });
+            }
+        }
+        for &(x, y) in &self.duplicate_tmaps {
+            issues.push(LayoutIssue::Duplicate { x, y });
+        }
+        issues.sort_by_key(|issue| match *issue {
+            LayoutIssue::MissingTDic { x, y, .. }
+            | LayoutIssue::OutOfBounds { x, y }
+            | LayoutIssue::Duplicate { x, y } => (y, x),
+        });
+        issues
+    }
+
+    // The hour and minute that the mission starts at.
+    pub fn time(&self) -> (u8, u8) {
+        self.time
+    }
+
+    // The raw clouds value; 0 if the key is missing. Most maps have 0, but some campaign
+    // maps do not. It is probably the cloud base, in feet like the rest of the file, but we
+    // have not checked that against the game yet.
+    pub fn clouds(&self) -> u32 {
+        self.clouds
+    }
+
+    // The raw wind as x (east) and z (north); 0, 0 if the key is missing. The unit is not
+    // known yet.
+    pub fn wind(&self) -> (i16, i16) {
+        self.wind
+    }
+
+    // Sky state `elapsed` after the start of the mission, for previewing lighting.
+    pub fn environment_at(&self, elapsed: Duration) -> Environment {
+        Environment::new(self.time, elapsed, self.wind)
+    }
+
+    // Per-sample height changes to make to the T2, as (x, z, delta), in the form expected by
+    // Terrain::apply_elevation_overrides. We have not found anything in a mission that edits
+    // the terrain: specials only carry map labels and objects that sit on platforms, like
+    // carriers, carry their own decks. So this is always empty for now.
+    pub fn elevation_overrides(&self) -> Vec<(u32, u32, i16)> {
+        Vec::new()
+    }
+
+    // Sum the geometry of every placed object's shape at its highest detail. Shapes are
+    // only loaded once, no matter how many times they are placed.
+    pub fn geometry_budget(&self, catalog: &Catalog) -> Fallible<GeometryBudget> {
+        let mut per_shape = HashMap::new();
+        let mut budget = GeometryBudget::default();
+        for info in &self.objects {
+            let name = match info.xt().ot().shape {
+                Some(ref name) => name.to_owned(),
+                None => continue,
+            };
+            if !per_shape.contains_key(&name) {
+                let shape_budget = GeometryBudget::for_shape(&name, catalog)?;
+                per_shape.insert(name.clone(), shape_budget);
+            }
+            budget += per_shape[&name];
+        }
+        Ok(budget)
+    }
+
     pub fn objects(&self) -> &Vec<ObjectInfo> {
         &self.objects
     }
 
+    // The raw side flags, indexed by nationality: 0 or 128. Empty if the map has no sides.
+    pub fn sides(&self) -> &[u8] {
+        self.side_table().unwrap_or(&[])
+    }
+
+    // The raw side flags, or None if the map does not have a sides table at all.
+    pub fn side_table(&self) -> Option<&[u8]> {
+        self.sides.as_deref()
+    }
+
+    // Which of the sides, sides2, sides3, or sides4 keys the table came from, if any.
+    pub fn sides_version(&self) -> Option<u8> {
+        self.sides_version
+    }
+
+    // Every entry in the sides table, with the nationality at that index and whether that
+    // nationality is flagged. Use Nationality::name to show them.
+    pub fn active_sides(&self) -> Vec<(Nationality, bool)> {
+        self.sides()
+            .iter()
+            .enumerate()
+            .map(|(i, &side)| {
+                let nationality =
+                    Nationality::from_ordinal(i).expect("sides are limited to 64 when parsed");
+                (nationality, side == 128)
+            })
+            .collect()
+    }
+
     // These are all of the terrains and map references in the base games.
     // FA:
     //     FA_2.LIB:
@@ -451,17 +767,12 @@ impl MissionMap {
     //     installdir: UKR.T2, $UKR[1-8].T2
     //     MM+M refs: ukr.T2, $ukr[1-8].T2
     fn find_t2_for_map(map_name: &str, catalog: &Catalog) -> Fallible<String> {
-        let raw = map_name.to_uppercase();
-
-        if catalog.exists(&raw) {
-            return Ok(raw);
-        }
-
-        // ~KURILE.T2 && ~TVIET.T2
-        if raw.starts_with('~') && catalog.exists(&raw[1..]) {
-            return Ok(raw[1..].to_owned());
+        // Includes ~KURILE.T2 && ~TVIET.T2, which are stored without the ~.
+        if let Some(name) = CatalogBuilder::find_name_ci(catalog, map_name) {
+            return Ok(name);
         }
 
+        let raw = map_name.to_uppercase();
         let parts = raw.split('.').collect::<Vec<&str>>();
         let base = parts[0];
         if base.len() == 5 {
@@ -492,9 +803,9 @@ impl MissionMap {
             layer_parts[1].to_uppercase() == "LAY",
             "expected LAY extension"
         );
-        let alt_layer_name = format!("{}{}.LAY", layer_parts[0], first_char).to_uppercase();
-        if catalog.exists(&alt_layer_name) {
-            return Ok(alt_layer_name);
+        let alt_layer_name = format!("{}{}.LAY", layer_parts[0], first_char);
+        if let Some(name) = CatalogBuilder::find_name_ci(catalog, &alt_layer_name) {
+            return Ok(name);
         }
         Ok(layer_name.to_uppercase())
     }
@@ -518,6 +829,135 @@ impl MissionMap {
 
         Ok(name.to_owned())
     }
+
+    // A fingerprint of the parsed mission, for caching and change detection. Only what we
+    // parse goes in, so comments and spacing do not change it. This is FNV-1a rather than
+    // the std hasher, which may change between Rust releases, so it is safe to store.
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = FnvHasher::default();
+        self.map_name.hash(&mut hasher);
+        self.t2_name.hash(&mut hasher);
+        self.layer_name.hash(&mut hasher);
+        (self.layer_index as u64).hash(&mut hasher);
+        let mut coords = self.tmaps.keys().collect::<Vec<_>>();
+        coords.sort();
+        for coord in coords {
+            let tmap = &self.tmaps[coord];
+            coord.hash(&mut hasher);
+            tmap.orientation.as_byte().hash(&mut hasher);
+            tmap.loc.hash(&mut hasher);
+        }
+        self.tdics.hash(&mut hasher);
+        self.clouds.hash(&mut hasher);
+        self.wind.hash(&mut hasher);
+        self.view.hash(&mut hasher);
+        self.time.hash(&mut hasher);
+        self.sides.hash(&mut hasher);
+        self.sides_version.hash(&mut hasher);
+        self.objects.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    // Find the numbered tile PICs for a terrain that none of the given missions lay down.
+    // Missions over other terrains are skipped. Tiles are matched by number, since some games
+    // zero-pad the names, as TLoc::resolve does. The result is sorted by name.
+    pub fn unused_tile_pics(
+        t2_name: &str,
+        missions: &[&MissionMap],
+        catalog: &Catalog,
+    ) -> Fallible<Vec<String>> {
+        let mut used_indices = HashSet::new();
+        let mut used_names = HashSet::new();
+        let mut bases = HashSet::new();
+        for mm in missions {
+            if !mm.t2_name.eq_ignore_ascii_case(t2_name) {
+                continue;
+            }
+            let base = mm.get_base_texture_name()?;
+            for tmap in mm.tmaps.values() {
+                match tmap.loc {
+                    TLoc::Index(i) => {
+                        used_indices.insert((base.clone(), i));
+                    }
+                    TLoc::Name(ref name) => {
+                        used_names.insert(name.to_uppercase());
+                    }
+                }
+            }
+            bases.insert(base);
+        }
+
+        let mut unused = Vec::new();
+        for base in &bases {
+            for name in catalog
+                .find_matching_names(&format!("{}*.PIC", base))?
+                .iter()
+            {
+                let name = name.to_string();
+                let index = &name[base.len()..name.len() - ".PIC".len()];
+                if index.is_empty() || !index.chars().all(|c| c.is_ascii_digit()) {
+                    continue;
+                }
+                let used = used_indices.contains(&(base.to_owned(), index.parse::<usize>()?))
+                    || used_names.contains(&name.to_uppercase());
+                if !used {
+                    unused.push(name);
+                }
+            }
+        }
+        unused.sort();
+        Ok(unused)
+    }
+}
+
+// Write the mission back out as MM text that from_str will read to the same content_hash.
+// Keys are written in roughly the order the game's files use: the header, then objects with
+// their routes, specials, and finally the tiles. Comments and historicalera are not kept by
+// from_str, so are not written; tiles are sorted by position.
+impl fmt::Display for MissionMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "textFormat")?;
+        writeln!(f, "map {}", self.map_name)?;
+        let stem = self.layer_name.trim_end_matches(".LAY").to_lowercase();
+        writeln!(f, "layer {}.LAY {}", stem, self.layer_index)?;
+        writeln!(f, "clouds {}", self.clouds)?;
+        writeln!(f, "wind {} {}", self.wind.0, self.wind.1)?;
+        writeln!(f, "view {} {} {}", self.view.0, self.view.1, self.view.2)?;
+        writeln!(f, "time {} {}", self.time.0, self.time.1)?;
+        if let (Some(sides), Some(version)) = (&self.sides, self.sides_version) {
+            if version == 1 {
+                writeln!(f, "sides")?;
+                for side in sides {
+                    writeln!(f, "\t{}", side)?;
+                }
+            } else {
+                writeln!(f, "sides{}", version)?;
+                for side in sides {
+                    writeln!(f, "\t${:02x}", side)?;
+                }
+            }
+            // The list runs until the first line that is not a side.
+            writeln!(f)?;
+        }
+        for obj in &self.objects {
+            write!(f, "{}", obj)?;
+        }
+        for special in &self.specials {
+            write!(f, "{}", special)?;
+        }
+        let mut coords = self.tmaps.keys().collect::<Vec<_>>();
+        coords.sort();
+        for &(x, y) in coords {
+            writeln!(f, "{}", self.tmaps[&(x, y)].to_line(x, y))?;
+        }
+        for tdic in &self.tdics {
+            writeln!(f, "tdic {}", tdic.n)?;
+            for row in &tdic.map {
+                writeln!(f, "{} {} {} {}", row[0], row[1], row[2], row[3])?;
+            }
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -525,6 +965,488 @@ mod tests {
     use super::*;
     use lib::{from_dos_string, CatalogBuilder};
 
+    fn parse_fragment(body: &str) -> Fallible<MissionMap> {
+        let catalog = Catalog::empty();
+        let type_manager = TypeManager::empty();
+        let header = "textFormat\nmap ~ukr1.T2\nlayer ukr.LAY 1\nview 0 0 0\ntime 12 0\n";
+        MissionMap::from_str(&(header.to_owned() + body), &type_manager, &catalog)
+    }
+
+    #[test]
+    fn it_can_parse_tmap_named_orientation() -> Fallible<()> {
+        let mm = parse_fragment("tmap_named foo 0 4 3\ntmap_named bar 4 4\n")?;
+        let rotated = mm.texture_map(0, 4).expect("tmap at 0,4");
+        assert_eq!(rotated.loc, TLoc::Name("FOO.PIC".to_owned()));
+        assert_eq!(rotated.orientation, MapOrientation::RotateCCW);
+        let plain = mm.texture_map(4, 4).expect("tmap at 4,4");
+        assert_eq!(plain.orientation, MapOrientation::Unk0);
+        Ok(())
+    }
+
+    #[test]
+    fn it_can_round_trip_tmap_named_orientation() -> Fallible<()> {
+        for n in 0..4 {
+            assert_eq!(MapOrientation::from_byte(n)?.as_byte(), n);
+        }
+
+        let tmap = TMap {
+            orientation: MapOrientation::RotateCCW,
+            loc: TLoc::Name("FOO.PIC".to_owned()),
+        };
+        let line = tmap.to_line(8, 12);
+        assert_eq!(line, "tmap_named foo 8 12 3");
+        let mm = parse_fragment(&(line + "\n"))?;
+        let parsed = mm.texture_map(8, 12).expect("tmap at 8,12");
+        assert_eq!(parsed.loc, tmap.loc);
+        assert_eq!(parsed.orientation, MapOrientation::RotateCCW);
+
+        let plain = TMap {
+            orientation: MapOrientation::Unk0,
+            loc: TLoc::Name("BAR.PIC".to_owned()),
+        };
+        assert_eq!(plain.to_line(0, 4), "tmap_named bar 0 4");
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_negative_tmap_coordinates() -> Fallible<()> {
+        assert!(parse_fragment("tmap_named foo 4 8\n").is_ok());
+        for body in &[
+            "tmap -4 8 0 0\n",
+            "tmap 4 -8 0 0\n",
+            "tmap_named foo -4 8\n",
+            "tmap_named foo 4 -8\n",
+        ] {
+            let err = parse_fragment(body)
+                .err()
+                .expect("negative tile coordinate");
+            assert!(err.to_string().starts_with("negative"), "{}", err);
+        }
+        assert!(parse_fragment("tmap_named foo 2 8\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn it_can_read_the_view() -> Fallible<()> {
+        let catalog = Catalog::empty();
+        let type_manager = TypeManager::empty();
+        let body = "textFormat\nmap ~ukr1.T2\nlayer ukr.LAY 1\nview 1000 2000 3000\ntime 6 0\n";
+        let mm = MissionMap::from_str(body, &type_manager, &catalog)?;
+        assert_eq!(mm.view(), (1000, 2000, 3000));
+        Ok(())
+    }
+
+    #[test]
+    fn it_can_validate_the_layout() -> Fallible<()> {
+        let (catalog, inputs) = CatalogBuilder::build_and_select(&["USNF:UKR.T2".to_owned()])?;
+        let terrain = Terrain::from_bytes(&catalog.read_sync(inputs[0])?)?;
+        assert_eq!(terrain.width(), 208);
+
+        let tdic = "tdic 0\n".to_owned() + &"0 0 0 0\n".repeat(8);
+        let mm = parse_fragment(&(tdic + "tmap 0 0 0 0\ntmap 4 0 0 0\ntmap 4 0 0 1\n"))?;
+        assert_eq!(
+            mm.validate_layout(&terrain),
+            vec![LayoutIssue::Duplicate { x: 4, y: 0 }]
+        );
+
+        let mm = parse_fragment("tmap_named foo 0 0\ntmap_named bar 1000 200\n")?;
+        assert_eq!(
+            mm.validate_layout(&terrain),
+            vec![LayoutIssue::OutOfBounds { x: 1000, y: 200 }]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn it_can_hash_mission_content() -> Fallible<()> {
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["FA:BAL.MM".to_owned()])?;
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let type_manager = TypeManager::empty();
+            let contents = from_dos_string(catalog.read_sync(fid)?);
+            let mm = MissionMap::from_str(&contents, &type_manager, &catalog)?;
+            let again = MissionMap::from_str(&contents, &type_manager, &catalog)?;
+            assert_eq!(mm.content_hash(), again.content_hash());
+
+            // Move the view over by prefixing its x with a 1.
+            let patched = contents.replacen("\nview ", "\nview 1", 1);
+            assert_ne!(patched, contents);
+            let patched = MissionMap::from_str(&patched, &type_manager, &catalog)?;
+            assert_ne!(mm.content_hash(), patched.content_hash());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_can_find_unused_tile_pics() -> Fallible<()> {
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["FA:*UKR*.MM".to_owned()])?;
+        let type_manager = TypeManager::empty();
+        let mut missions = Vec::new();
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let contents = from_dos_string(catalog.read_sync(fid)?);
+            missions.push(MissionMap::from_str(&contents, &type_manager, &catalog)?);
+        }
+        assert!(!missions.is_empty());
+        let missions = missions.iter().collect::<Vec<_>>();
+
+        let t2_name = missions[0].t2_name();
+        let base = missions[0].get_base_texture_name()?;
+        let unused = MissionMap::unused_tile_pics(t2_name, &missions, &catalog)?;
+        let all = catalog
+            .find_matching_names(&format!("{}*.PIC", base))?
+            .iter()
+            .map(|name| name.to_string())
+            .collect::<HashSet<_>>();
+        for name in &unused {
+            assert!(all.contains(name));
+            // BAL05.PIC and BAL5.PIC are the same tile.
+            let index = name[base.len()..name.len() - ".PIC".len()].parse::<usize>()?;
+            for mm in missions.iter().filter(|mm| mm.t2_name() == t2_name) {
+                for tmap in mm.texture_maps() {
+                    assert_ne!(tmap.loc, TLoc::Index(index));
+                }
+            }
+        }
+        assert!(MissionMap::unused_tile_pics("NOSUCH.T2", &missions, &catalog)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn it_can_move_the_sun() -> Fallible<()> {
+        let catalog = Catalog::empty();
+        let type_manager = TypeManager::empty();
+        let body = "textFormat\nmap ~ukr1.T2\nlayer ukr.LAY 1\nview 0 0 0\ntime 6 0\n";
+        let mm = MissionMap::from_str(body, &type_manager, &catalog)?;
+        assert_eq!(mm.time(), (6, 0));
+
+        let dawn = mm.environment_at(Duration::from_secs(0));
+        let noon = mm.environment_at(Duration::from_secs(6 * 60 * 60));
+        assert!(dawn.sun_direction[1].abs() < 0.001);
+        assert!(dawn.sun_direction[0] > 0.999);
+        assert!(noon.sun_direction[1] > 0.999);
+        assert!((dawn.sun_direction - noon.sun_direction).norm() > 1f32);
+        assert_eq!(noon.seconds_of_day, 12 * 60 * 60);
+
+        let next_dawn = mm.environment_at(Duration::from_secs(24 * 60 * 60));
+        assert_eq!(next_dawn.seconds_of_day, dawn.seconds_of_day);
+        Ok(())
+    }
+
+    #[test]
+    fn it_has_no_elevation_overrides() -> Fallible<()> {
+        let mm = parse_fragment(
+            "special\n\tpos 1347582 0 315393\n\tname Sea of Japan\n\tcolor 48\n\ticon -1\n\tflags $0\n\t.\n",
+        )?;
+        assert!(mm.elevation_overrides().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn it_can_peek_the_map_name() -> Fallible<()> {
+        // Nothing after the map is looked at, so a broken tail is fine.
+        let body = "textFormat\nmap ~ukr1.T2\nlayer ukr.LAY 1\nobj\n\tname \x01unterminated\n";
+        let name = MissionMap::peek_map_name(body)?;
+        assert_eq!(name, MapName::parse("~ukr1.T2")?);
+        assert_eq!(name.prefix(), Some('~'));
+        assert_eq!(name.base(), "UKR");
+        assert_eq!(name.number(), Some(1));
+        assert!(MissionMap::peek_map_name("textFormat\nlayer ukr.LAY 1\n").is_err());
+        assert!(MissionMap::peek_map_name("textFormat\nmap\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn it_can_compute_a_geometry_budget() -> Fallible<()> {
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["FA:BAL.MM".to_owned()])?;
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let type_manager = TypeManager::empty();
+            let contents = from_dos_string(catalog.read_sync(fid)?);
+            let mm = MissionMap::from_str(&contents, &type_manager, &catalog)?;
+            let budget = mm.geometry_budget(&catalog)?;
+            assert!(budget.vertices > 0);
+            assert!(budget.triangles > 0);
+
+            let mut expect = GeometryBudget::default();
+            for info in mm.objects() {
+                if let Some(ref name) = info.xt().ot().shape {
+                    expect += GeometryBudget::for_shape(name, &catalog)?;
+                }
+            }
+            assert_eq!(budget, expect);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_can_resolve_object_types() -> Fallible<()> {
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["FA:*.MM".to_owned()])?;
+        let mut planes = 0;
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let type_manager = TypeManager::empty();
+            let contents = from_dos_string(catalog.read_sync(fid)?);
+            let mm = MissionMap::from_str(&contents, &type_manager, &catalog)?;
+            for info in mm.objects() {
+                let xt = info.resolve_type(&type_manager, &catalog)?;
+                assert_eq!(xt.ot().file_name(), info.xt().ot().file_name());
+                assert_eq!(xt.is_pt(), info.type_name().ends_with(".PT"));
+                if xt.is_pt() {
+                    assert!(xt.pt().is_ok());
+                    planes += 1;
+                }
+            }
+        }
+        assert!(planes > 0);
+        Ok(())
+    }
+
+    #[test]
+    fn it_can_list_active_sides() -> Fallible<()> {
+        let mm = parse_fragment("sides2\n\t$80\n\t$00\n\t$80\n\n")?;
+        assert_eq!(mm.sides_version(), Some(2));
+        let sides = mm.active_sides();
+        assert_eq!(sides.len(), mm.sides().len());
+        assert_eq!(sides[0], (Nationality::Unk0, true));
+        assert_eq!(sides[0].0.name(), Some("Neutral"));
+        assert_eq!(sides[1], (Nationality::Unk1, false));
+        assert_eq!(sides[1].0.name(), Some("USA"));
+        assert_eq!(sides[2], (Nationality::Unk2, true));
+
+        let too_many = "sides2\n".to_owned() + &"\t$00\n".repeat(65) + "\n";
+        assert!(parse_fragment(&too_many).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn it_has_one_sides_length_per_version() -> Fallible<()> {
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["*:*.MM".to_owned()])?;
+        let type_manager = TypeManager::empty();
+        let mut lengths = HashMap::new();
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let contents = from_dos_string(catalog.read_sync(fid)?);
+            let mm = MissionMap::from_str(&contents, &type_manager, &catalog)?;
+            if let Some(version) = mm.sides_version() {
+                let sides = mm.active_sides();
+                assert!(sides
+                    .iter()
+                    .all(|(nationality, _)| nationality.name().is_some()));
+                let expect = *lengths.entry(version).or_insert_with(|| sides.len());
+                assert_eq!(sides.len(), expect);
+            }
+        }
+        assert!(!lengths.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn it_attaches_success_flags_to_the_next_obj() -> Fallible<()> {
+        assert!(parse_fragment("map_obj_success_flags 1 $2\nwind 0 0\n").is_err());
+        assert!(parse_fragment("wind 0 0\nmap_obj_success_flags 1 $2\n").is_err());
+
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["FA:BAL.MM".to_owned()])?;
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let type_manager = TypeManager::empty();
+            let contents = from_dos_string(catalog.read_sync(fid)?);
+            let at = contents
+                .match_indices("\nobj\n")
+                .nth(1)
+                .expect("two objs")
+                .0;
+            let patched = format!(
+                "{}\nmap_obj_success_flags -1 $3{}",
+                &contents[..at],
+                &contents[at..]
+            );
+            let mm = MissionMap::from_str(&patched, &type_manager, &catalog)?;
+            for (i, info) in mm.objects().iter().enumerate() {
+                if i == 1 {
+                    assert_eq!(info.success_flags(), Some((-1, 3)));
+                } else {
+                    assert_eq!(info.success_flags(), None);
+                }
+            }
+            let again = MissionMap::from_str(&mm.to_string(), &type_manager, &catalog)?;
+            assert_eq!(again.objects()[1].success_flags(), Some((-1, 3)));
+            assert_eq!(mm.content_hash(), again.content_hash());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_can_resolve_tile_pics() -> Fallible<()> {
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["FA:BAL.MM".to_owned()])?;
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let type_manager = TypeManager::empty();
+            let contents = from_dos_string(catalog.read_sync(fid)?);
+            let mm = MissionMap::from_str(&contents, &type_manager, &catalog)?;
+            let base = mm.get_base_texture_name()?;
+            for tmap in mm.texture_maps() {
+                let pic = tmap.loc.resolve(&base, &catalog)?;
+                let name = catalog.stat_sync(pic)?.name;
+                assert!(name.ends_with(".PIC"));
+                assert!(name.starts_with(&base) || tmap.loc.is_named());
+            }
+
+            let err = TLoc::Index(9).resolve("NOSUCH", &catalog).unwrap_err();
+            assert!(err.to_string().contains("NOSUCH9.PIC, NOSUCH09.PIC"));
+            let err = TLoc::Name("NOSUCH.PIC".to_owned()).resolve(&base, &catalog);
+            assert!(err.unwrap_err().to_string().ends_with("NOSUCH.PIC"));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_warns_about_missing_tdics() -> Fallible<()> {
+        let tdic = "tdic 0\n".to_owned() + &"0 0 0 0\n".repeat(8);
+        let body = tdic + "tmap 0 0 0 0\ntmap 8 4 3 0\ntmap 4 0 1 0\n";
+        let mm = parse_fragment(&body)?;
+        assert_eq!(
+            mm.warnings(),
+            &[
+                MmWarning::MissingTDic {
+                    x: 4,
+                    y: 0,
+                    index: 1
+                },
+                MmWarning::MissingTDic {
+                    x: 8,
+                    y: 4,
+                    index: 3
+                },
+            ]
+        );
+        assert!(mm.texture_map(0, 0).is_some());
+
+        let catalog = Catalog::empty();
+        let type_manager = TypeManager::empty();
+        let header = "textFormat\nmap ~ukr1.T2\nlayer ukr.LAY 1\nview 0 0 0\ntime 12 0\n";
+        assert!(
+            MissionMap::from_str_strict(&(header.to_owned() + &body), &type_manager, &catalog)
+                .is_err()
+        );
+        let mm = MissionMap::from_str_strict(header, &type_manager, &catalog)?;
+        assert!(mm.warnings().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn it_can_parse_clouds() -> Fallible<()> {
+        let mm = parse_fragment("wind 0 0\n")?;
+        assert_eq!(mm.clouds(), 0);
+        let mm = parse_fragment("clouds 8000\nwind 12 -3\n")?;
+        assert_eq!(mm.clouds(), 8000);
+        assert_eq!(mm.wind(), (12, -3));
+        assert!(mm.to_string().contains("\nclouds 8000\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn it_can_parse_without_sides() -> Fallible<()> {
+        let mm = parse_fragment("wind 0 0\n")?;
+        assert_eq!(mm.side_table(), None);
+        assert_eq!(mm.sides_version(), None);
+        assert!(mm.sides().is_empty());
+        assert!(mm.active_sides().is_empty());
+
+        let mm = parse_fragment("sides\n\t0\n\t128\n\n")?;
+        assert_eq!(mm.side_table(), Some(&[0u8, 128u8][..]));
+        Ok(())
+    }
+
+    #[test]
+    fn it_can_read_waypoints() -> Fallible<()> {
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["FA:*.MM".to_owned()])?;
+        let mut multi_leg = 0;
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let type_manager = TypeManager::empty();
+            let contents = from_dos_string(catalog.read_sync(fid)?);
+            let mm = MissionMap::from_str(&contents, &type_manager, &catalog)?;
+
+            // Pull the w_pos2 of every route out of the text, by the alias in its w_for.
+            let mut routes = HashMap::new();
+            let mut route = Vec::new();
+            for line in contents.lines() {
+                let parts = line.split_whitespace().collect::<Vec<_>>();
+                match parts.first() {
+                    Some(&"w_pos2") => route.push((
+                        parts[3].parse::<f32>()?,
+                        parts[4].parse::<f32>()?,
+                        parts[5].parse::<f32>()?,
+                    )),
+                    Some(&"w_for") => {
+                        routes.insert(parts[1].parse::<i32>()?, route.split_off(0));
+                    }
+                    _ => {}
+                }
+            }
+
+            for info in mm.objects() {
+                let waypoints = match info.waypoints() {
+                    Some(waypoints) => waypoints,
+                    None => continue,
+                };
+                let expect = &routes[&info.alias()];
+                assert_eq!(waypoints.len(), expect.len());
+                for (i, wp) in waypoints.iter().enumerate() {
+                    assert_eq!(wp.index as usize, i);
+                }
+                let (first, last) = (
+                    waypoints[0].position(),
+                    waypoints.last().unwrap().position(),
+                );
+                assert_eq!((first.x, first.y, first.z), expect[0]);
+                assert_eq!((last.x, last.y, last.z), expect[expect.len() - 1]);
+                if waypoints.len() > 1 {
+                    multi_leg += 1;
+                }
+            }
+        }
+        assert!(multi_leg > 0);
+        Ok(())
+    }
+
+    #[test]
+    fn it_can_write_mm_files() -> Fallible<()> {
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["*:*.MM".to_owned()])?;
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            let game = label.split(':').last().unwrap();
+            let meta = catalog.stat_sync(fid)?;
+            if game == "ATFGOLD"
+                && (meta.name.contains("UKR") || meta.name == "KURILE.MM" || meta.name == "VIET.MM")
+            {
+                continue;
+            }
+            if meta.name == "$VARF.MM" {
+                continue;
+            }
+
+            catalog.set_default_label(&label);
+            let type_manager = TypeManager::empty();
+            let contents = from_dos_string(catalog.read_sync(fid)?);
+            let mm = MissionMap::from_str(&contents, &type_manager, &catalog)?;
+            let written = mm.to_string();
+            let again = MissionMap::from_str(&written, &type_manager, &catalog)?;
+            assert_eq!(again, mm, "{}", meta.name);
+            assert_eq!(written, again.to_string());
+        }
+        Ok(())
+    }
+
     #[test]
     fn it_can_parse_all_mm_files() -> Fallible<()> {
         let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["*:*.MM".to_owned()])?;
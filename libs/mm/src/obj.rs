@@ -16,34 +16,149 @@ use crate::{util::maybe_hex, waypoint::Waypoint};
 use catalog::Catalog;
 use failure::{bail, err_msg, Fallible};
 use nalgebra::{Point3, Unit, UnitQuaternion, Vector3};
-use std::f32::consts::PI;
+use std::{
+    f32::consts::PI,
+    fmt,
+    hash::{Hash, Hasher},
+};
 use xt::{TypeManager, TypeRef};
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+// The names of the nationalities that a mission's sides table is indexed by. This order is
+// reconstructed from memory of FA's nation list and still needs to be checked against the
+// names the game itself shows.
+const NATIONALITY_NAMES: [&str; 64] = [
+    "Neutral",
+    "USA",
+    "UK",
+    "France",
+    "Germany",
+    "Italy",
+    "Spain",
+    "Netherlands",
+    "Belgium",
+    "Norway",
+    "Denmark",
+    "Greece",
+    "Turkey",
+    "Israel",
+    "Egypt",
+    "Jordan",
+    "Saudi Arabia",
+    "Kuwait",
+    "Iraq",
+    "Iran",
+    "Syria",
+    "Libya",
+    "Russia",
+    "Ukraine",
+    "Belarus",
+    "Kazakhstan",
+    "Poland",
+    "Czech Republic",
+    "Hungary",
+    "Romania",
+    "Bulgaria",
+    "Serbia",
+    "Croatia",
+    "Bosnia",
+    "China",
+    "Taiwan",
+    "Japan",
+    "North Korea",
+    "South Korea",
+    "Vietnam",
+    "India",
+    "Pakistan",
+    "Afghanistan",
+    "Cuba",
+    "Canada",
+    "Mexico",
+    "Argentina",
+    "Brazil",
+    "Chile",
+    "Australia",
+    "South Africa",
+    "Sweden",
+    "Finland",
+    "Switzerland",
+    "Austria",
+    "Portugal",
+    "UAE",
+    "Qatar",
+    "Oman",
+    "Yemen",
+    "Algeria",
+    "Morocco",
+    "Indonesia",
+    "Philippines",
+];
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub enum Nationality {
     Unk0 = 0,
     Unk1 = 1,
+    Unk2 = 2,
     Unk3 = 3,
     Unk4 = 4,
     Unk5 = 5,
+    Unk6 = 6,
     Unk7 = 7,
     Unk8 = 8,
+    Unk9 = 9,
     Unk10 = 10,
     Unk11 = 11,
     Unk12 = 12,
     Unk13 = 13,
+    Unk14 = 14,
     Unk15 = 15,
     Unk16 = 16,
     Unk17 = 17,
+    Unk18 = 18,
+    Unk19 = 19,
+    Unk20 = 20,
     Unk21 = 21,
     Unk22 = 22,
+    Unk23 = 23,
+    Unk24 = 24,
     Unk25 = 25,
     Unk26 = 26,
     Unk27 = 27,
     Unk28 = 28,
+    Unk29 = 29,
+    Unk30 = 30,
+    Unk31 = 31,
+    Unk32 = 32,
+    Unk33 = 33,
+    Unk34 = 34,
+    Unk35 = 35,
     Unk36 = 36,
+    Unk37 = 37,
+    Unk38 = 38,
     Unk39 = 39,
     Unk40 = 40,
+    Unk41 = 41,
+    Unk42 = 42,
+    Unk43 = 43,
+    Unk44 = 44,
+    Unk45 = 45,
+    Unk46 = 46,
+    Unk47 = 47,
+    Unk48 = 48,
+    Unk49 = 49,
+    Unk50 = 50,
+    Unk51 = 51,
+    Unk52 = 52,
+    Unk53 = 53,
+    Unk54 = 54,
+    Unk55 = 55,
+    Unk56 = 56,
+    Unk57 = 57,
+    Unk58 = 58,
+    Unk59 = 59,
+    Unk60 = 60,
+    Unk61 = 61,
+    Unk62 = 62,
+    Unk63 = 63,
     Unk130 = 130,
     Unk131 = 131,
     Unk137 = 137,
@@ -61,31 +176,72 @@ pub enum Nationality {
 }
 
 impl Nationality {
-    fn from_ordinal(n: usize) -> Fallible<Self> {
+    pub fn from_ordinal(n: usize) -> Fallible<Self> {
         Ok(match n {
             0 => Nationality::Unk0,
             1 => Nationality::Unk1,
+            2 => Nationality::Unk2,
             3 => Nationality::Unk3,
             4 => Nationality::Unk4,
             5 => Nationality::Unk5,
+            6 => Nationality::Unk6,
             7 => Nationality::Unk7,
             8 => Nationality::Unk8,
+            9 => Nationality::Unk9,
             10 => Nationality::Unk10,
             11 => Nationality::Unk11,
             12 => Nationality::Unk12,
             13 => Nationality::Unk13,
+            14 => Nationality::Unk14,
             15 => Nationality::Unk15,
             16 => Nationality::Unk16,
             17 => Nationality::Unk17,
+            18 => Nationality::Unk18,
+            19 => Nationality::Unk19,
+            20 => Nationality::Unk20,
             21 => Nationality::Unk21,
             22 => Nationality::Unk22,
+            23 => Nationality::Unk23,
+            24 => Nationality::Unk24,
             25 => Nationality::Unk25,
             26 => Nationality::Unk26,
             27 => Nationality::Unk27,
             28 => Nationality::Unk28,
+            29 => Nationality::Unk29,
+            30 => Nationality::Unk30,
+            31 => Nationality::Unk31,
+            32 => Nationality::Unk32,
+            33 => Nationality::Unk33,
+            34 => Nationality::Unk34,
+            35 => Nationality::Unk35,
             36 => Nationality::Unk36,
+            37 => Nationality::Unk37,
+            38 => Nationality::Unk38,
             39 => Nationality::Unk39,
             40 => Nationality::Unk40,
+            41 => Nationality::Unk41,
+            42 => Nationality::Unk42,
+            43 => Nationality::Unk43,
+            44 => Nationality::Unk44,
+            45 => Nationality::Unk45,
+            46 => Nationality::Unk46,
+            47 => Nationality::Unk47,
+            48 => Nationality::Unk48,
+            49 => Nationality::Unk49,
+            50 => Nationality::Unk50,
+            51 => Nationality::Unk51,
+            52 => Nationality::Unk52,
+            53 => Nationality::Unk53,
+            54 => Nationality::Unk54,
+            55 => Nationality::Unk55,
+            56 => Nationality::Unk56,
+            57 => Nationality::Unk57,
+            58 => Nationality::Unk58,
+            59 => Nationality::Unk59,
+            60 => Nationality::Unk60,
+            61 => Nationality::Unk61,
+            62 => Nationality::Unk62,
+            63 => Nationality::Unk63,
             130 => Nationality::Unk130,
             131 => Nationality::Unk131,
             137 => Nationality::Unk137,
@@ -103,16 +259,26 @@ impl Nationality {
             _ => bail!("nationality: do not know {}", n),
         })
     }
+
+    // The country's name, for the nationalities that can appear in a sides table.
+    pub fn name(&self) -> Option<&'static str> {
+        NATIONALITY_NAMES.get(self.clone() as usize).cloned()
+    }
 }
 
 #[derive(Clone, Debug)]
 #[allow(dead_code)]
 pub struct ObjectInfo {
+    type_name: String,
     xt: TypeRef,
     name: Option<String>,
     pos: Point3<f32>,
+    // The heading in degrees, as written; angle is built from it.
+    heading: i32,
     angle: UnitQuaternion<f32>,
     nationality: Nationality,
+    // Which of the nationality, nationality2, or nationality3 keys it came from.
+    nationality_version: u8,
     flags: u16,
     speed: f32,
     alias: i32,
@@ -122,6 +288,50 @@ pub struct ObjectInfo {
     search_dist: Option<u32>,
     // PT only.
     waypoints: Option<Vec<Waypoint>>,
+    // From a map_obj_success_flags just before the obj.
+    success_flags: Option<(i32, u8)>,
+}
+
+// Floats are hashed by their bits. The type is covered by its name, since the loaded
+// type is shared with every other object of the same kind.
+impl Hash for ObjectInfo {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.type_name.hash(state);
+        self.name.hash(state);
+        for v in self.pos.iter().chain(self.angle.as_ref().coords.iter()) {
+            v.to_bits().hash(state);
+        }
+        self.nationality.hash(state);
+        self.flags.hash(state);
+        self.speed.to_bits().hash(state);
+        self.alias.hash(state);
+        self.skill.hash(state);
+        self.react.hash(state);
+        self.search_dist.hash(state);
+        self.waypoints.hash(state);
+        self.success_flags.hash(state);
+    }
+}
+
+// As with Hash, the type is compared by its name. Everything else must match exactly.
+impl PartialEq for ObjectInfo {
+    fn eq(&self, other: &Self) -> bool {
+        self.type_name == other.type_name
+            && self.name == other.name
+            && self.pos == other.pos
+            && self.heading == other.heading
+            && self.angle == other.angle
+            && self.nationality == other.nationality
+            && self.nationality_version == other.nationality_version
+            && self.flags == other.flags
+            && self.speed == other.speed
+            && self.alias == other.alias
+            && self.skill == other.skill
+            && self.react == other.react
+            && self.search_dist == other.search_dist
+            && self.waypoints == other.waypoints
+            && self.success_flags == other.success_flags
+    }
 }
 
 impl ObjectInfo {
@@ -134,8 +344,10 @@ impl ObjectInfo {
         let mut type_name = None;
         let mut name = None;
         let mut pos = None;
+        let mut heading = 0;
         let mut angle = UnitQuaternion::identity();
         let mut nationality = None;
+        let mut nationality_version = 1;
         let mut flags = 0u16;
         let mut speed = 0f32;
         let mut alias = 0i32;
@@ -180,6 +392,7 @@ impl ObjectInfo {
                     // No entities are tilted or pitched, only rotated.
                     assert_eq!(is[1], 0);
                     assert_eq!(is[2], 0);
+                    heading = is[0];
                     angle = UnitQuaternion::from_axis_angle(
                         &Unit::new_unchecked(Vector3::new(0f32, 1f32, 0f32)),
                         -(is[0] as f32 * PI / 180f32),
@@ -189,10 +402,12 @@ impl ObjectInfo {
                     nationality = Some(Nationality::from_ordinal(parts[1].parse::<usize>()?)?)
                 }
                 "nationality2" => {
-                    nationality = Some(Nationality::from_ordinal(parts[1].parse::<usize>()?)?)
+                    nationality = Some(Nationality::from_ordinal(parts[1].parse::<usize>()?)?);
+                    nationality_version = 2;
                 }
                 "nationality3" => {
-                    nationality = Some(Nationality::from_ordinal(parts[1].parse::<usize>()?)?)
+                    nationality = Some(Nationality::from_ordinal(parts[1].parse::<usize>()?)?);
+                    nationality_version = 3;
                 }
                 "flags" => flags = maybe_hex::<u16>(parts[1])?,
                 "speed" => speed = parts[1].parse::<i32>()? as f32,
@@ -214,18 +429,16 @@ impl ObjectInfo {
             }
             *offset += 1;
         }
+        let type_name = type_name
+            .ok_or_else(|| err_msg(format!("mm:obj: type not set in obj ending {}", *offset)))?
+            .to_uppercase();
         Ok(ObjectInfo {
-            xt: type_manager.load(
-                &type_name
-                    .ok_or_else(|| {
-                        err_msg(format!("mm:obj: type not set in obj ending {}", *offset))
-                    })?
-                    .to_uppercase(),
-                catalog,
-            )?,
+            xt: type_manager.load(&type_name, catalog)?,
+            type_name,
             name,
             pos: pos
                 .ok_or_else(|| err_msg(format!("mm:obj: pos not set in obj ending {}", *offset)))?,
+            heading,
             angle,
             nationality: nationality.ok_or_else(|| {
                 err_msg(format!(
@@ -233,6 +446,7 @@ impl ObjectInfo {
                     *offset
                 ))
             })?,
+            nationality_version,
             flags,
             speed,
             alias,
@@ -240,6 +454,7 @@ impl ObjectInfo {
             react,
             search_dist,
             waypoints: None,
+            success_flags: None,
         })
     }
 
@@ -247,6 +462,21 @@ impl ObjectInfo {
         self.waypoints = Some(waypoints);
     }
 
+    pub(crate) fn set_success_flags(&mut self, success_flags: (i32, u8)) {
+        self.success_flags = Some(success_flags);
+    }
+
+    // The two values of the map_obj_success_flags that came just before this object, if
+    // any. We do not know what they mean yet.
+    pub fn success_flags(&self) -> Option<(i32, u8)> {
+        self.success_flags
+    }
+
+    // The route, for objects that fly one, in order.
+    pub fn waypoints(&self) -> Option<&[Waypoint]> {
+        self.waypoints.as_deref()
+    }
+
     pub fn alias(&self) -> i32 {
         self.alias
     }
@@ -259,6 +489,17 @@ impl ObjectInfo {
         self.xt.clone()
     }
 
+    // The upper-cased file name of the object's type, e.g. F22.PT or SA6.NT.
+    pub fn type_name(&self) -> &str {
+        &self.type_name
+    }
+
+    // Look the type up again through the given manager; this is a cache hit for the
+    // manager the mission was loaded with, but lets callers swap in another one.
+    pub fn resolve_type(&self, type_manager: &TypeManager, catalog: &Catalog) -> Fallible<TypeRef> {
+        type_manager.load(&self.type_name, catalog)
+    }
+
     pub fn position(&self) -> Point3<f32> {
         self.pos
     }
@@ -266,4 +507,56 @@ impl ObjectInfo {
     pub fn angle(&self) -> &UnitQuaternion<f32> {
         &self.angle
     }
+
+    pub fn nationality(&self) -> &Nationality {
+        &self.nationality
+    }
+}
+
+// Write the object back out as an obj block, followed by its route, if it has one. Keys that
+// were not in the original are left out, except for the ones that default to zero.
+impl fmt::Display for ObjectInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some((a, b)) = self.success_flags {
+            writeln!(f, "map_obj_success_flags {} {}", a, b)?;
+        }
+        writeln!(f, "obj")?;
+        writeln!(f, "\ttype {}", self.type_name)?;
+        if let Some(name) = &self.name {
+            writeln!(f, "\tname \x01{}\x01", name)?;
+        }
+        writeln!(
+            f,
+            "\tpos {} {} {}",
+            self.pos.x as i32, self.pos.y as i32, self.pos.z as i32
+        )?;
+        writeln!(f, "\tangle {} 0 0", self.heading)?;
+        let key = match self.nationality_version {
+            1 => "nationality".to_owned(),
+            n => format!("nationality{}", n),
+        };
+        writeln!(f, "\t{} {}", key, self.nationality.clone() as usize)?;
+        writeln!(f, "\tflags ${:x}", self.flags)?;
+        writeln!(f, "\tspeed {}", self.speed as i32)?;
+        writeln!(f, "\talias {}", self.alias)?;
+        if let Some(skill) = self.skill {
+            writeln!(f, "\tskill {}", skill)?;
+        }
+        if let Some((a, b, c)) = self.react {
+            writeln!(f, "\treact ${:x} ${:x} ${:x}", a, b, c)?;
+        }
+        if let Some(search_dist) = self.search_dist {
+            writeln!(f, "\tsearchDist {}", search_dist)?;
+        }
+        writeln!(f, "\t.")?;
+        if let Some(waypoints) = &self.waypoints {
+            writeln!(f, "waypoint2 {}", waypoints.len())?;
+            for waypoint in waypoints {
+                write!(f, "{}", waypoint)?;
+            }
+            writeln!(f, "\tw_for {}", self.alias)?;
+            writeln!(f, "\t.")?;
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,113 @@
+// This file is part of OpenFA.
+//
+// OpenFA is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// OpenFA is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
+use failure::{bail, Fallible};
+use std::collections::HashMap;
+
+const HEADER: &str = "textFormat";
+
+// Quick missions (the ~Q files) are not complete missions: the game glues several fragments
+// together and fills in variables like <aaa> and <tank> to pick what to fight.
+pub struct QuickMission;
+
+impl QuickMission {
+    // Concatenate the fragments, keeping only the first textFormat header, and replace every
+    // <name> with substitutions[name]. It is an error to leave a variable unfilled.
+    pub fn assemble(fragments: &[&str], substitutions: &HashMap<&str, &str>) -> Fallible<String> {
+        let mut out = String::new();
+        for (i, fragment) in fragments.iter().enumerate() {
+            let mut body = *fragment;
+            if i > 0 && body.starts_with(HEADER) {
+                body = body[HEADER.len()..].trim_start_matches(&['\r', '\n'][..]);
+            }
+            Self::substitute(body, substitutions, &mut out)?;
+            if !out.is_empty() && !out.ends_with('\n') {
+                out.push('\n');
+            }
+        }
+        Ok(out)
+    }
+
+    fn substitute(
+        mut body: &str,
+        substitutions: &HashMap<&str, &str>,
+        out: &mut String,
+    ) -> Fallible<()> {
+        while let Some(start) = body.find('<') {
+            out.push_str(&body[..start]);
+            body = &body[start..];
+            let name = match Self::variable_name(body) {
+                Some(name) => name,
+                None => {
+                    // A stray < in a name or comment; leave it alone.
+                    out.push('<');
+                    body = &body[1..];
+                    continue;
+                }
+            };
+            match substitutions.get(name) {
+                Some(value) => out.push_str(value),
+                None => bail!("no substitution for <{}> in quick mission", name),
+            }
+            body = &body[name.len() + 2..];
+        }
+        out.push_str(body);
+        Ok(())
+    }
+
+    fn variable_name(s: &str) -> Option<&str> {
+        let end = s.find('>')?;
+        let name = &s[1..end];
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return None;
+        }
+        Some(name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::MissionMap;
+    use catalog::Catalog;
+    use xt::TypeManager;
+
+    #[test]
+    fn it_can_assemble_a_quick_mission() -> Fallible<()> {
+        let fragments = [
+            "textFormat\nmap ~ukr1.T2\nlayer ukr.LAY 1\n",
+            "textFormat\r\nview <x> 0 <z>\ntime <hour> 30",
+            "wind <wind> 0 ; a <comment> about the 1<2 wind\n",
+        ];
+        let mut substitutions = HashMap::new();
+        substitutions.insert("x", "1000");
+        substitutions.insert("z", "2000");
+        substitutions.insert("hour", "9");
+        substitutions.insert("wind", "-4");
+        substitutions.insert("comment", "note");
+
+        let body = QuickMission::assemble(&fragments, &substitutions)?;
+        assert_eq!(body.matches(HEADER).count(), 1);
+        assert!(body.contains("view 1000 0 2000\ntime 9 30\n"));
+        assert!(body.contains("a note about the 1<2 wind"));
+
+        let mm = MissionMap::from_str(&body, &TypeManager::empty(), &Catalog::empty())?;
+        assert_eq!(mm.time(), (9, 30));
+        assert_eq!(mm.environment_at(Default::default()).wind, (-4, 0));
+
+        substitutions.remove("hour");
+        assert!(QuickMission::assemble(&fragments, &substitutions).is_err());
+        Ok(())
+    }
+}
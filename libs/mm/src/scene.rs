@@ -0,0 +1,131 @@
+// This file is part of OpenFA.
+//
+// OpenFA is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// OpenFA is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
+use crate::{obj::Nationality, MapOrientation, MissionMap};
+use failure::Fallible;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SceneTile {
+    pub x: u32,
+    pub y: u32,
+    pub pic: String,
+    pub orientation: MapOrientation,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SceneObject {
+    pub type_name: String,
+    pub shape: Option<String>,
+    pub name: Option<String>,
+    // Feet from the corner of the map, as in the mission file.
+    pub position: [f32; 3],
+    // A quaternion as i, j, k, w.
+    pub rotation: [f32; 4],
+    pub nationality: Nationality,
+}
+
+// Everything needed to draw a mission, flattened into plain data that does not refer back
+// to the catalog or type manager.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MissionScene {
+    pub t2_name: String,
+    pub layer_name: String,
+    pub layer_index: usize,
+    // Sorted by y, then x.
+    pub tiles: Vec<SceneTile>,
+    pub objects: Vec<SceneObject>,
+    pub sides: Vec<u8>,
+    pub time: (u8, u8),
+    pub wind: (i16, i16),
+}
+
+impl MissionMap {
+    pub fn to_scene(&self) -> Fallible<MissionScene> {
+        let base = self.get_base_texture_name()?;
+        let mut tiles = self
+            .tmaps
+            .iter()
+            .map(|(&(x, y), tmap)| SceneTile {
+                x,
+                y,
+                pic: tmap.loc.pic_file(&base),
+                orientation: tmap.orientation,
+            })
+            .collect::<Vec<_>>();
+        tiles.sort_by_key(|tile| (tile.y, tile.x));
+
+        let objects = self
+            .objects
+            .iter()
+            .map(|info| {
+                let p = info.position();
+                let q = info.angle().as_ref().coords;
+                SceneObject {
+                    type_name: info.type_name().to_owned(),
+                    shape: info.xt().ot().shape.clone(),
+                    name: info.name(),
+                    position: [p.x, p.y, p.z],
+                    rotation: [q[0], q[1], q[2], q[3]],
+                    nationality: info.nationality().clone(),
+                }
+            })
+            .collect();
+
+        Ok(MissionScene {
+            t2_name: self.t2_name.clone(),
+            layer_name: self.layer_name.clone(),
+            layer_index: self.layer_index,
+            tiles,
+            objects,
+            sides: self.sides().to_vec(),
+            time: self.time,
+            wind: self.wind,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use lib::{from_dos_string, CatalogBuilder};
+    use xt::TypeManager;
+
+    #[test]
+    fn it_can_flatten_a_mission() -> Fallible<()> {
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["FA:BAL.MM".to_owned()])?;
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let type_manager = TypeManager::empty();
+            let contents = from_dos_string(catalog.read_sync(fid)?);
+            let mm = MissionMap::from_str(&contents, &type_manager, &catalog)?;
+            let scene = mm.to_scene()?;
+
+            assert_eq!(scene.t2_name, mm.t2_name());
+            assert_eq!(scene.objects.len(), mm.objects().len());
+            assert_eq!(scene.tiles.len(), mm.texture_maps().count());
+            assert_eq!(scene.sides, mm.sides());
+            assert_eq!(scene.time, mm.time());
+            for tile in &scene.tiles {
+                let tmap = mm.texture_map(tile.x, tile.y).expect("a tile");
+                assert_eq!(tile.orientation, tmap.orientation);
+            }
+            for (object, info) in scene.objects.iter().zip(mm.objects()) {
+                assert_eq!(object.type_name, info.type_name());
+                assert_eq!(object.position[0], info.position().x);
+            }
+        }
+        Ok(())
+    }
+}
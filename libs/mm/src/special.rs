@@ -15,8 +15,9 @@
 use crate::util::maybe_hex;
 use failure::{bail, err_msg, Fallible};
 use nalgebra::Point3;
+use std::fmt;
 
-#[allow(dead_code)]
+#[derive(Debug, PartialEq)]
 pub struct SpecialInfo {
     pos: Point3<f32>,
     name: String,
@@ -95,3 +96,20 @@ impl SpecialInfo {
         })
     }
 }
+
+impl fmt::Display for SpecialInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "special")?;
+        writeln!(
+            f,
+            "\tpos {} {} {}",
+            self.pos.x as i32, self.pos.y as i32, self.pos.z as i32
+        )?;
+        // The name is kept with its ^A quotes.
+        writeln!(f, "\tname {}", self.name)?;
+        writeln!(f, "\tcolor {}", self.color)?;
+        writeln!(f, "\ticon {}", self.icon)?;
+        writeln!(f, "\tflags ${:x}", self.flags)?;
+        writeln!(f, "\t.")
+    }
+}
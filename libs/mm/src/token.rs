@@ -0,0 +1,140 @@
+// This file is part of OpenFA.
+//
+// OpenFA is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// OpenFA is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
+use crate::util::maybe_hex;
+use failure::{bail, Fallible};
+
+// Names in MM and M files are wrapped in ^A.
+const STRING_DELIMITER: char = '\x01';
+
+// One whitespace separated value in an MM or M file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MValue {
+    // Keys and file names: map, ~ukr1.T2, etc.
+    Word(String),
+    // Decimal or $ prefixed hex numbers.
+    Int(i64),
+    // A ^A delimited name, without the delimiters.
+    Str(String),
+    // A lone period, which closes obj, special, and waypoint blocks.
+    End,
+}
+
+impl MValue {
+    // Lazily split the file into values, so that tools that only need the top of a file
+    // do not have to pay for the whole thing.
+    pub fn stream(s: &str) -> MValueStream {
+        MValueStream {
+            rest: s,
+            failed: false,
+        }
+    }
+
+    pub fn tokenize(s: &str) -> Fallible<Vec<MValue>> {
+        Self::stream(s).collect()
+    }
+
+    fn from_word(word: &str) -> Fallible<Self> {
+        if word == "." {
+            return Ok(MValue::End);
+        }
+        if word.starts_with('$') {
+            return Ok(MValue::Int(maybe_hex::<i64>(word)?));
+        }
+        Ok(match word.parse::<i64>() {
+            Ok(n) => MValue::Int(n),
+            Err(_) => MValue::Word(word.to_owned()),
+        })
+    }
+}
+
+pub struct MValueStream<'a> {
+    rest: &'a str,
+    failed: bool,
+}
+
+impl<'a> MValueStream<'a> {
+    fn next_value(&mut self) -> Fallible<Option<MValue>> {
+        self.rest = self.rest.trim_start();
+        if self.rest.is_empty() {
+            return Ok(None);
+        }
+        if self.rest.starts_with(STRING_DELIMITER) {
+            let body = &self.rest[1..];
+            let end = match body.find(STRING_DELIMITER) {
+                Some(end) => end,
+                None => bail!("unterminated name in mission file"),
+            };
+            self.rest = &body[end + 1..];
+            return Ok(Some(MValue::Str(body[..end].to_owned())));
+        }
+        let end = self
+            .rest
+            .find(char::is_whitespace)
+            .unwrap_or(self.rest.len());
+        let word = &self.rest[..end];
+        self.rest = &self.rest[end..];
+        Ok(Some(MValue::from_word(word)?))
+    }
+}
+
+impl<'a> Iterator for MValueStream<'a> {
+    type Item = Fallible<MValue>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        match self.next_value() {
+            Ok(value) => value.map(Ok),
+            Err(e) => {
+                self.failed = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_can_stream_values() -> Fallible<()> {
+        let body = "textFormat\nmap ~ukr1.T2\nlayer ukr.LAY 1\nsides2\n\t$80\n\
+                    special\n\tpos 1347582 0 -315393\n\tname \x01Sea of Japan\x01\n\t.\n";
+        let streamed = MValue::stream(body).collect::<Fallible<Vec<_>>>()?;
+        assert_eq!(streamed, MValue::tokenize(body)?);
+        assert_eq!(
+            &streamed[..4],
+            &[
+                MValue::Word("textFormat".to_owned()),
+                MValue::Word("map".to_owned()),
+                MValue::Word("~ukr1.T2".to_owned()),
+                MValue::Word("layer".to_owned()),
+            ]
+        );
+        assert_eq!(streamed[7], MValue::Int(0x80));
+        assert_eq!(streamed[12], MValue::Int(-315_393));
+        assert_eq!(streamed[14], MValue::Str("Sea of Japan".to_owned()));
+        assert_eq!(streamed[15], MValue::End);
+
+        // Stop early without touching the rest of the file.
+        let mut stream = MValue::stream("map ~foo.T2 \x01broken");
+        assert_eq!(stream.nth(1).unwrap()?, MValue::Word("~foo.T2".to_owned()));
+        assert!(stream.next().unwrap().is_err());
+        assert!(stream.next().is_none());
+        Ok(())
+    }
+}
@@ -13,7 +13,11 @@
 // You should have received a copy of the GNU General Public License
 // along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
 use failure::{bail, err_msg, Fallible};
-use nalgebra::Vector3;
+use nalgebra::{Point3, Vector3};
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+};
 
 // w_index 0
 // w_flags 1
@@ -26,7 +30,7 @@ use nalgebra::Vector3;
 // w_searchDist 0
 // w_preferredTargetId 0
 // w_name ^A^A
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 #[allow(dead_code)]
 pub struct Waypoint {
     pub index: u8,
@@ -42,6 +46,22 @@ pub struct Waypoint {
     // name: ""
 }
 
+impl Hash for Waypoint {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+        self.flags.hash(state);
+        self.goal.hash(state);
+        self.next.hash(state);
+        for v in self.pos.iter() {
+            v.to_bits().hash(state);
+        }
+        self.speed.hash(state);
+        self.wng.hash(state);
+        self.react.hash(state);
+        self.search_dist.hash(state);
+    }
+}
+
 impl Waypoint {
     pub(crate) fn from_lines(lines: &[&str], offset: &mut usize) -> Fallible<Self> {
         let mut index = None;
@@ -161,3 +181,51 @@ impl Waypoint {
         })
     }
 }
+
+impl Waypoint {
+    pub fn position(&self) -> Point3<f32> {
+        Point3::from(self.pos)
+    }
+
+    pub fn speed(&self) -> usize {
+        self.speed
+    }
+
+    pub fn flags(&self) -> u8 {
+        self.flags
+    }
+
+    // Whether this leg is one of the flight's goals, e.g. the target of a strike.
+    pub fn is_goal(&self) -> bool {
+        self.goal
+    }
+}
+
+impl fmt::Display for Waypoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "\tw_index {}", self.index)?;
+        writeln!(f, "\tw_flags {}", self.flags)?;
+        writeln!(f, "\tw_goal {}", self.goal as u8)?;
+        writeln!(f, "\tw_next {}", self.next as u8)?;
+        writeln!(
+            f,
+            "\tw_pos2 0 0 {} {} {}",
+            self.pos[0], self.pos[1], self.pos[2]
+        )?;
+        writeln!(f, "\tw_speed {}", self.speed)?;
+        writeln!(
+            f,
+            "\tw_wng {} {} {} {}",
+            self.wng[0], self.wng[1], self.wng[2], self.wng[3]
+        )?;
+        writeln!(
+            f,
+            "\tw_react {} {} {}",
+            self.react[0], self.react[1], self.react[2]
+        )?;
+        writeln!(f, "\tw_searchDist {}", self.search_dist)?;
+        writeln!(f, "\tw_preferredTargetId 0")?;
+        writeln!(f, "\tw_name \x01\x01")?;
+        writeln!(f, "\t.")
+    }
+}
@@ -12,6 +12,7 @@
 //
 // You should have received a copy of the GNU General Public License
 // along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
+use catalog::Catalog;
 use failure::{ensure, Fallible};
 use image::{Pixel, Rgb, Rgba};
 use std::{borrow::Cow, fs::File, io::Write};
@@ -37,6 +38,24 @@ impl Palette {
         Self::from_bytes_prescaled(&arr)
     }
 
+    // Load a raw VGA palette by name, without any of the layer overlays that terrains add.
+    pub fn from_catalog(name: &str, catalog: &Catalog) -> Fallible<Self> {
+        Self::from_bytes(&catalog.read_name_sync(name)?)
+    }
+
+    // The palette that everything but the terrain is drawn with.
+    pub fn system(catalog: &Catalog) -> Fallible<Self> {
+        Self::from_catalog("PALETTE.PAL", catalog)
+    }
+
+    // The system palette of a specific game, by catalog label, regardless of the default
+    // label. The palettes differ between games and some games (e.g. ATFGOLD) carry missions
+    // from other games without their assets, so colors are only right if we use the palette
+    // of the game that the asset came from.
+    pub fn for_game(game_label: &str, catalog: &Catalog) -> Fallible<Self> {
+        Self::from_bytes(&catalog.read_labeled_name_sync(game_label, "PALETTE.PAL")?)
+    }
+
     pub fn from_bytes(data: &[u8]) -> Fallible<Self> {
         // The VGA palette contains 6 bit colors, so we need to scale by 4 and add the bottom 2 bits.
         ensure!(data.len() % 3 == 0, "expected data to divide cleanly by 3");
@@ -235,6 +254,7 @@ impl<'a> From<Palette> for Cow<'a, Palette> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use lib::CatalogBuilder;
     use std::fs;
     use std::io::prelude::*;
 
@@ -253,6 +273,50 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn it_can_load_the_system_palette() -> Fallible<()> {
+        let (mut catalog, inputs) =
+            CatalogBuilder::build_and_select(&["FA:PALETTE.PAL".to_owned()])?;
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let pal = Palette::system(&catalog)?;
+            assert_eq!(pal.color_count, 256);
+            assert_eq!(
+                pal.rgb(1)?,
+                Rgb {
+                    data: [252, 0, 252]
+                }
+            );
+            let by_name = Palette::from_catalog("PALETTE.PAL", &catalog)?;
+            assert_eq!(by_name.as_bytes(), pal.as_bytes());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_can_load_palettes_per_game() -> Fallible<()> {
+        let (catalog, inputs) = CatalogBuilder::build_and_select(&[
+            "FA:PALETTE.PAL".to_owned(),
+            "USNF97:PALETTE.PAL".to_owned(),
+        ])?;
+        let mut palettes = Vec::new();
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            let pal = Palette::for_game(&label, &catalog)?;
+            assert_eq!(pal.color_count, 256);
+            assert_eq!(
+                pal.as_bytes(),
+                Palette::from_bytes(&catalog.read_sync(fid)?)?.as_bytes()
+            );
+            palettes.push(pal);
+        }
+        if palettes.len() == 2 {
+            assert_ne!(palettes[0].as_bytes(), palettes[1].as_bytes());
+        }
+        Ok(())
+    }
+
     #[test]
     fn it_can_be_empty() -> Fallible<()> {
         let empty = Vec::new();
@@ -63,6 +63,8 @@ pub struct SectionInfo {
     pub virtual_address: u32,
     pub virtual_size: u32,
     pub size_of_raw_data: u32,
+    // Where the section starts in the file.
+    pub pointer_to_raw_data: u32,
 }
 
 impl SectionInfo {
@@ -71,6 +73,7 @@ impl SectionInfo {
             virtual_address: header.virtual_address(),
             virtual_size: header.virtual_size(),
             size_of_raw_data: header.size_of_raw_data(),
+            pointer_to_raw_data: header.pointer_to_raw_data(),
         }
     }
 }
@@ -541,6 +544,16 @@ impl PE {
         Ok(())
     }
 
+    // Undo relocate(target) on a copy of the code, e.g. to write it back out to disk.
+    pub fn unrelocate_code(&self, target: u32, code: &mut [u8]) {
+        let delta = RelocationDelta::new(self.image_base + self.code_vaddr, target);
+        for &reloc in self.relocs.iter() {
+            let at = reloc as usize;
+            let word = u32::from_le_bytes([code[at], code[at + 1], code[at + 2], code[at + 3]]);
+            code[at..at + 4].copy_from_slice(&delta.apply(word).to_le_bytes());
+        }
+    }
+
     // Relocates a relative pointer to a section or thunk to an absolute address,
     // given the target load address.
     pub fn relocate_thunk_pointer(&self, target: u32, addr: u32) -> u32 {
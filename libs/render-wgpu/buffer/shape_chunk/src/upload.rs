@@ -25,7 +25,7 @@ use log::trace;
 use memoffset::offset_of;
 use pal::Palette;
 use pic::Pic;
-use sh::{Facet, FacetFlags, Instr, RawShape, VertexBuf, X86Code, X86Trampoline, SHAPE_LOAD_BASE};
+use sh::{Facet, FacetFlags, Instr, RawShape, VertexBuf, X86Code, X86Trampoline};
 use std::{
     collections::{HashMap, HashSet},
     f32::{INFINITY, NEG_INFINITY},
@@ -841,7 +841,7 @@ impl<'a> ShapeUploader<'a> {
             for operand in &instr.operands {
                 if let i386::Operand::Memory(memref) = operand {
                     if let Ok(tramp) = sh.lookup_trampoline_by_offset(
-                        memref.displacement.wrapping_sub(SHAPE_LOAD_BASE as i32) as u32,
+                        sh.to_code_offset(memref.displacement as u32) as u32,
                     ) {
                         out.insert(tramp.name.as_str(), tramp);
                     }
@@ -860,7 +860,7 @@ impl<'a> ShapeUploader<'a> {
         for instr in &x86.bytecode.instrs {
             if instr.memonic == i386::Memonic::Push {
                 if let i386::Operand::Imm32s(v) = instr.operands[0] {
-                    push_value = (v as u32).wrapping_sub(SHAPE_LOAD_BASE);
+                    push_value = sh.to_code_offset(v as u32) as u32;
                 }
             }
             if instr.memonic == i386::Memonic::Return {
@@ -946,11 +946,11 @@ impl<'a> ShapeUploader<'a> {
 
         for &(value, flags) in &TOGGLE_TABLE[trampoline.name.as_str()] {
             interp.map_value(trampoline.mem_location, value);
-            let exit_info = interp.interpret(x86.code_offset(SHAPE_LOAD_BASE))?;
+            let exit_info = interp.interpret(x86.code_offset(sh.instr_base_address()))?;
             let (name, args) = exit_info.ok_trampoline()?;
             ensure!(name == "do_start_interp", "unexpected trampoline return");
             ensure!(args.len() == 1, "unexpected arg count");
-            if unmask.at_offset() == args[0].wrapping_sub(SHAPE_LOAD_BASE) as usize {
+            if unmask.at_offset() == sh.to_code_offset(args[0]) {
                 prop_man.add_or_update_toggle_flags(
                     unmask.unwrap_unmask_target()?,
                     flags,
@@ -990,7 +990,7 @@ impl<'a> ShapeUploader<'a> {
         interp.add_trampoline(num_loaded.mem_location, &num_loaded.name, 1);
 
         for &(value, flags) in &TOGGLE_TABLE["_SAMcount"] {
-            let exit_info = interp.interpret(x86.code_offset(0xAA00_0000u32))?;
+            let exit_info = interp.interpret(x86.code_offset(sh.instr_base_address()))?;
             let (name, args) = exit_info.ok_trampoline()?;
             ensure!(name == "@HARDNumLoaded@8", "unexpected num_loaded request");
             ensure!(args.len() == 1, "unexpected arg count");
@@ -1000,7 +1000,7 @@ impl<'a> ShapeUploader<'a> {
             let (name, args) = exit_info.ok_trampoline()?;
             ensure!(name == "do_start_interp", "unexpected trampoline return");
             ensure!(args.len() == 1, "unexpected arg count");
-            if unmask.at_offset() == args[0].wrapping_sub(SHAPE_LOAD_BASE) as usize {
+            if unmask.at_offset() == sh.to_code_offset(args[0]) {
                 prop_man.add_or_update_toggle_flags(
                     unmask.unwrap_unmask_target()?,
                     flags,
@@ -1134,8 +1134,8 @@ impl<'a> ShapeUploader<'a> {
         transformers.push(Transformer {
             xform_id,
             vm: interp,
-            code_offset: x86.code_offset(SHAPE_LOAD_BASE),
-            data_offset: SHAPE_LOAD_BASE + xform.at_offset() as u32 + 2u32,
+            code_offset: x86.code_offset(sh.instr_base_address()),
+            data_offset: sh.instr_base_address() + xform.at_offset() as u32 + 2u32,
             inputs,
             xform_base,
         });
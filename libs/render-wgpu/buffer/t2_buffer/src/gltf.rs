@@ -0,0 +1,210 @@
+// This file is part of OpenFA.
+//
+// OpenFA is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// OpenFA is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
+use failure::Fallible;
+use gltf_json::{
+    accessor::{ComponentType, GenericComponentType, Type},
+    buffer::{Target, View},
+    image::MimeType,
+    material::PbrMetallicRoughness,
+    mesh::{Mode, Primitive, Semantic},
+    scene::Node,
+    texture::Info,
+    validation::Checked::Valid,
+    Accessor, Asset, Buffer, Image, Index, Material, Mesh, Root, Scene, Texture, Value,
+};
+use image::{DynamicImage, ImageOutputFormat};
+use std::collections::HashMap;
+use zerocopy::AsBytes;
+
+fn data_uri(mime: &str, data: &[u8]) -> String {
+    format!("data:{};base64,{}", mime, base64::encode(data))
+}
+
+fn push_view(bytes: &mut Vec<u8>, data: &[u8], target: Option<Target>) -> View {
+    // Keep every view 4 byte aligned so that float accessors are valid.
+    while bytes.len() % 4 != 0 {
+        bytes.push(0);
+    }
+    let view = View {
+        buffer: Index::new(0),
+        byte_length: data.len() as u32,
+        byte_offset: Some(bytes.len() as u32),
+        byte_stride: None,
+        name: None,
+        target: target.map(Valid),
+        extensions: None,
+        extras: Default::default(),
+    };
+    bytes.extend_from_slice(data);
+    view
+}
+
+fn make_accessor(
+    view: u32,
+    count: usize,
+    component_type: ComponentType,
+    type_: Type,
+    bounds: Option<(Value, Value)>,
+) -> Accessor {
+    let (min, max) = match bounds {
+        Some((min, max)) => (Some(min), Some(max)),
+        None => (None, None),
+    };
+    Accessor {
+        buffer_view: Some(Index::new(view)),
+        byte_offset: 0,
+        count: count as u32,
+        component_type: Valid(GenericComponentType(component_type)),
+        extensions: None,
+        extras: Default::default(),
+        type_: Valid(type_),
+        min,
+        max,
+        name: None,
+        normalized: false,
+        sparse: None,
+    }
+}
+
+// Build a self-contained glTF document for a single textured triangle mesh. All buffer and
+// image data is embedded in the document as data uris so that the Root can be written out
+// as a single .gltf file.
+pub(crate) fn build_textured_mesh(
+    name: &str,
+    positions: &[[f32; 3]],
+    tex_coords: &[[f32; 2]],
+    indices: &[u32],
+    texture: &DynamicImage,
+) -> Fallible<Root> {
+    assert_eq!(positions.len(), tex_coords.len());
+
+    let mut lo = [std::f32::MAX; 3];
+    let mut hi = [std::f32::MIN; 3];
+    for p in positions {
+        for i in 0..3 {
+            lo[i] = lo[i].min(p[i]);
+            hi[i] = hi[i].max(p[i]);
+        }
+    }
+
+    let mut bytes = Vec::new();
+    let views = vec![
+        push_view(&mut bytes, positions.as_bytes(), Some(Target::ArrayBuffer)),
+        push_view(&mut bytes, tex_coords.as_bytes(), Some(Target::ArrayBuffer)),
+        push_view(
+            &mut bytes,
+            indices.as_bytes(),
+            Some(Target::ElementArrayBuffer),
+        ),
+    ];
+    let accessors = vec![
+        make_accessor(
+            0,
+            positions.len(),
+            ComponentType::F32,
+            Type::Vec3,
+            Some((Value::from(lo.to_vec()), Value::from(hi.to_vec()))),
+        ),
+        make_accessor(1, tex_coords.len(), ComponentType::F32, Type::Vec2, None),
+        make_accessor(2, indices.len(), ComponentType::U32, Type::Scalar, None),
+    ];
+
+    let mut png = Vec::new();
+    texture.write_to(&mut png, ImageOutputFormat::PNG)?;
+
+    let mut attributes = HashMap::new();
+    attributes.insert(Valid(Semantic::Positions), Index::new(0));
+    attributes.insert(Valid(Semantic::TexCoords(0)), Index::new(1));
+
+    Ok(Root {
+        accessors,
+        asset: Asset {
+            generator: Some("OpenFA".to_owned()),
+            ..Default::default()
+        },
+        buffers: vec![Buffer {
+            byte_length: bytes.len() as u32,
+            name: None,
+            uri: Some(data_uri("application/octet-stream", &bytes)),
+            extensions: None,
+            extras: Default::default(),
+        }],
+        buffer_views: views,
+        images: vec![Image {
+            buffer_view: None,
+            mime_type: Some(MimeType("image/png".to_owned())),
+            name: None,
+            uri: Some(data_uri("image/png", &png)),
+            extensions: None,
+            extras: Default::default(),
+        }],
+        textures: vec![Texture {
+            name: None,
+            sampler: None,
+            source: Index::new(0),
+            extensions: None,
+            extras: Default::default(),
+        }],
+        materials: vec![Material {
+            pbr_metallic_roughness: PbrMetallicRoughness {
+                base_color_texture: Some(Info {
+                    index: Index::new(0),
+                    tex_coord: 0,
+                    extensions: None,
+                    extras: Default::default(),
+                }),
+                ..Default::default()
+            },
+            ..Default::default()
+        }],
+        meshes: vec![Mesh {
+            extensions: None,
+            extras: Default::default(),
+            name: Some(name.to_owned()),
+            primitives: vec![Primitive {
+                attributes,
+                extensions: None,
+                extras: Default::default(),
+                indices: Some(Index::new(2)),
+                material: Some(Index::new(0)),
+                mode: Valid(Mode::Triangles),
+                targets: None,
+            }],
+            weights: None,
+        }],
+        nodes: vec![Node {
+            camera: None,
+            children: None,
+            extensions: None,
+            extras: Default::default(),
+            matrix: None,
+            mesh: Some(Index::new(0)),
+            name: Some(name.to_owned()),
+            rotation: None,
+            scale: None,
+            translation: None,
+            skin: None,
+            weights: None,
+        }],
+        scenes: vec![Scene {
+            extensions: None,
+            extras: Default::default(),
+            name: None,
+            nodes: vec![Index::new(0)],
+        }],
+        scene: Some(Index::new(0)),
+        ..Default::default()
+    })
+}
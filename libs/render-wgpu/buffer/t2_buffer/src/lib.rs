@@ -13,10 +13,11 @@
 // You should have received a copy of the GNU General Public License
 // along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
 
+mod gltf;
 mod t2_buffer;
 mod texture_atlas;
 
 pub use crate::{
-    t2_buffer::{T2Buffer, Vertex as T2Vertex},
-    texture_atlas::TextureAtlas,
+    t2_buffer::{T2Buffer, T2Tile, Vertex as T2Vertex},
+    texture_atlas::{AtlasError, TextureAtlas},
 };
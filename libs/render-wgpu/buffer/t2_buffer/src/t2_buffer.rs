@@ -12,10 +12,11 @@
 //
 // You should have received a copy of the GNU General Public License
 // along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
-use crate::texture_atlas::TextureAtlas;
+use crate::{gltf::build_textured_mesh, texture_atlas::TextureAtlas};
 use catalog::Catalog;
-use failure::Fallible;
+use failure::{ensure, Fallible};
 use gpu::GPU;
+use image::DynamicImage;
 use lay::Layer;
 use log::trace;
 use memoffset::offset_of;
@@ -96,6 +97,18 @@ impl Vertex {
 
         tmp
     }
+
+    // A bare vertex, e.g. for building a flat grid to displace on the CPU.
+    pub fn at(position: [f32; 3]) -> Self {
+        Self {
+            position,
+            ..Default::default()
+        }
+    }
+
+    pub fn position(&self) -> [f32; 3] {
+        self.position
+    }
 }
 
 // Hold our working state.
@@ -107,6 +120,8 @@ struct T2BufferFactory<'a> {
     memo_normal: HashMap<[(u32, u32); 3], Vector3<f32>>,
     memo_position: HashMap<(u32, u32), Vector3<f32>>,
     memo_vert: HashMap<(u32, u32), Vertex>,
+    memo_tex_coord: HashMap<(u32, u32), [[f32; 2]; 25]>,
+    memo_untextured: HashSet<(u32, u32)>,
 }
 
 impl<'a> T2BufferFactory<'a> {
@@ -118,15 +133,47 @@ impl<'a> T2BufferFactory<'a> {
             memo_position: HashMap::new(),
             memo_normal: HashMap::new(),
             memo_vert: HashMap::new(),
+            memo_tex_coord: HashMap::new(),
+            memo_untextured: HashSet::new(),
         }
     }
 
-    fn build(&mut self, gpu: &mut GPU) -> Fallible<Arc<RefCell<T2Buffer>>> {
+    // Build the terrain as tiles of tile_size samples on a side. If tile_size is None, the
+    // whole terrain goes up to the GPU immediately as a single tile.
+    fn build(&mut self, tile_size: Option<u32>, gpu: &mut GPU) -> Fallible<Arc<RefCell<T2Buffer>>> {
         let terrain = Terrain::from_bytes(&self.catalog.read_name_sync(&self.mm.t2_name())?)?;
         let palette = self.load_palette()?;
         let (atlas, bind_group_layout, bind_group) = self.create_atlas(&palette, gpu)?;
-        let (vertex_buffer, index_buffer, index_count) =
-            self.upload_terrain_textured_simple(&terrain, &atlas, &palette, gpu)?;
+
+        let layout = if let Some(tile_size) = tile_size {
+            ensure!(
+                tile_size > 0 && tile_size % 4 == 0,
+                "t2 tiles must be a whole number of patches"
+            );
+            tile_layout(terrain.width(), terrain.height(), tile_size)
+        } else {
+            vec![(0..terrain.width(), 0..terrain.height())]
+        };
+        let mut tiles = Vec::with_capacity(layout.len());
+        for (x_range, z_range) in layout {
+            let (verts, indices) =
+                self.build_patches(&terrain, &atlas, &palette, x_range.clone(), z_range.clone());
+            let mut tile = T2Tile {
+                x_range,
+                z_range,
+                index_count: indices.len() as u32,
+                verts,
+                indices,
+                buffers: None,
+            };
+            if tile_size.is_none() {
+                // Never evicted, so there is no reason to keep a copy around.
+                tile.upload(gpu);
+                tile.verts = Vec::new();
+                tile.indices = Vec::new();
+            }
+            tiles.push(tile);
+        }
 
         let mut positions = HashMap::new();
         mem::swap(&mut positions, &mut self.memo_position);
@@ -134,14 +181,22 @@ impl<'a> T2BufferFactory<'a> {
         let mut normals = HashMap::new();
         mem::swap(&mut normals, &mut self.memo_normal);
 
+        let mut tex_coords = HashMap::new();
+        mem::swap(&mut tex_coords, &mut self.memo_tex_coord);
+
+        let mut untextured = HashSet::new();
+        mem::swap(&mut untextured, &mut self.memo_untextured);
+
         Ok(Arc::new(RefCell::new(T2Buffer {
             bind_group_layout,
             bind_group,
-            vertex_buffer,
-            index_buffer,
-            index_count,
+            tiles,
+            tiled: tile_size.is_some(),
             positions,
             normals,
+            tex_coords,
+            untextured,
+            atlas_img: atlas.img,
             terrain,
         })))
     }
@@ -204,8 +259,8 @@ impl<'a> T2BufferFactory<'a> {
                 if loaded.contains(&tmap.loc) {
                     continue;
                 }
-                let name = tmap.loc.pic_file(&texture_base_name);
-                let data = self.catalog.read_name_sync(&name)?;
+                let fid = tmap.loc.resolve(&texture_base_name, &self.catalog)?;
+                let data = self.catalog.read_sync(fid)?;
                 let pic = Pic::decode(palette, &data)?;
                 loaded.insert(tmap.loc.clone());
                 pics.push((tmap.loc.clone(), pic));
@@ -454,13 +509,14 @@ impl<'a> T2BufferFactory<'a> {
         verts.push(vert);
     }
 
-    fn upload_terrain_textured_simple(
+    fn build_patches(
         &mut self,
         terrain: &Terrain,
         atlas: &TextureAtlas,
         palette: &Palette,
-        gpu: &GPU,
-    ) -> Fallible<(wgpu::Buffer, wgpu::Buffer, u32)> {
+        x_range: Range<u32>,
+        z_range: Range<u32>,
+    ) -> (Vec<Vertex>, Vec<u32>) {
         let mut verts = Vec::new();
         let mut indices = Vec::new();
 
@@ -486,8 +542,8 @@ impl<'a> T2BufferFactory<'a> {
             }
         };
 
-        for zi_base in (0..terrain.height()).step_by(4) {
-            for xi_base in (0..terrain.width()).step_by(4) {
+        for zi_base in z_range.step_by(4) {
+            for xi_base in x_range.clone().step_by(4) {
                 let base = verts.len() as u32;
 
                 // Upload one patch of vertices, possibly with a texture.
@@ -495,6 +551,10 @@ impl<'a> T2BufferFactory<'a> {
                     .mm
                     .texture_map(xi_base, zi_base)
                     .map(|tmap| (&atlas.frames[&tmap.loc], &tmap.orientation));
+                if frame_info.is_none() {
+                    self.memo_untextured.insert((xi_base, zi_base));
+                }
+                let mut patch_tex_coords = [[0f32; 2]; 25];
                 for z_off in 0..=4 {
                     for x_off in 0..=4 {
                         let zi = zi_base + z_off;
@@ -506,30 +566,149 @@ impl<'a> T2BufferFactory<'a> {
                             })
                             .unwrap_or([0f32, 0f32]);
 
+                        patch_tex_coords[(z_off * 5 + x_off) as usize] = tex_coord;
                         self.compute_at(terrain, palette, xi, zi, tex_coord, &mut verts);
                     }
                 }
+                self.memo_tex_coord
+                    .insert((xi_base, zi_base), patch_tex_coords);
                 push_patch_indices(base, &mut indices);
             }
         }
 
-        let vertex_buffer = gpu.push_slice("t2-buffer-vertices", &verts, wgpu::BufferUsage::all());
-        let index_buffer = gpu.push_slice("t2-buffer-indices", &indices, wgpu::BufferUsage::all());
-        Ok((vertex_buffer, index_buffer, indices.len() as u32))
+        (verts, indices)
+    }
+}
+
+// Split a width x height sample grid into tiles of at most tile_size samples on a side,
+// in row major order. Edge tiles are clipped to the grid.
+fn tile_layout(width: u32, height: u32, tile_size: u32) -> Vec<(Range<u32>, Range<u32>)> {
+    let mut tiles = Vec::new();
+    for z0 in (0..height).step_by(tile_size as usize) {
+        for x0 in (0..width).step_by(tile_size as usize) {
+            tiles.push((
+                x0..(x0 + tile_size).min(width),
+                z0..(z0 + tile_size).min(height),
+            ));
+        }
+    }
+    tiles
+}
+
+// Lay out a 5x5 grid of vertices for each 4x4 patch, as we do for the GPU, so that the
+// far row and column of every patch keep the texture coordinates of that patch's own
+// atlas frame instead of sharing a vertex with the next patch over.
+fn patch_grid(
+    patches: &[(u32, u32, [[f32; 2]; 25])],
+    position_at: impl Fn(u32, u32) -> [f32; 3],
+) -> (Vec<[f32; 3]>, Vec<[f32; 2]>, Vec<u32>) {
+    let mut positions = Vec::with_capacity(patches.len() * 25);
+    let mut tex_coords = Vec::with_capacity(patches.len() * 25);
+    let mut indices = Vec::with_capacity(patches.len() * 16 * 6);
+    for (xi_base, zi_base, patch_tex_coords) in patches {
+        let base = positions.len() as u32;
+        for z_off in 0..=4 {
+            for x_off in 0..=4 {
+                positions.push(position_at(xi_base + x_off, zi_base + z_off));
+            }
+        }
+        tex_coords.extend_from_slice(patch_tex_coords);
+        for z_off in 0..4 {
+            for x_off in 0..4 {
+                let nw = base + z_off * 5 + x_off;
+                let ne = nw + 1;
+                let sw = nw + 5;
+                let se = sw + 1;
+                indices.extend_from_slice(&[nw, sw, ne, ne, sw, se]);
+            }
+        }
+    }
+    (positions, tex_coords, indices)
+}
+
+// True if a tile centered at center_hm, in x and z, is within radius_hm of the camera.
+fn tile_in_radius(center_hm: [f32; 2], camera: &Point3<f32>, radius_hm: f32) -> bool {
+    let dx = center_hm[0] - camera.coords[0];
+    let dz = center_hm[1] - camera.coords[2];
+    dx * dx + dz * dz <= radius_hm * radius_hm
+}
+
+// A block of terrain patches with its own vertex and index buffers. For large terrains we
+// keep the vertices on the CPU and only upload the tiles near the camera.
+pub struct T2Tile {
+    x_range: Range<u32>,
+    z_range: Range<u32>,
+    index_count: u32,
+    verts: Vec<Vertex>,
+    indices: Vec<u32>,
+    buffers: Option<(wgpu::Buffer, wgpu::Buffer)>,
+}
+
+impl T2Tile {
+    // The samples covered by this tile.
+    pub fn x_range(&self) -> Range<u32> {
+        self.x_range.clone()
+    }
+
+    pub fn z_range(&self) -> Range<u32> {
+        self.z_range.clone()
+    }
+
+    pub fn is_resident(&self) -> bool {
+        self.buffers.is_some()
+    }
+
+    pub fn vertex_buffer(&self) -> Option<&wgpu::Buffer> {
+        self.buffers
+            .as_ref()
+            .map(|(vertex_buffer, _)| vertex_buffer)
+    }
+
+    pub fn index_buffer(&self) -> Option<&wgpu::Buffer> {
+        self.buffers.as_ref().map(|(_, index_buffer)| index_buffer)
+    }
+
+    pub fn index_range(&self) -> Range<u32> {
+        0..self.index_count
+    }
+
+    fn upload(&mut self, gpu: &GPU) {
+        if self.buffers.is_some() {
+            return;
+        }
+        let vertex_buffer =
+            gpu.push_slice("t2-buffer-vertices", &self.verts, wgpu::BufferUsage::all());
+        let index_buffer =
+            gpu.push_slice("t2-buffer-indices", &self.indices, wgpu::BufferUsage::all());
+        self.buffers = Some((vertex_buffer, index_buffer));
+    }
+
+    fn evict(&mut self) {
+        self.buffers = None;
     }
 }
 
 pub struct T2Buffer {
     bind_group_layout: wgpu::BindGroupLayout,
     bind_group: wgpu::BindGroup,
-    vertex_buffer: wgpu::Buffer,
-    index_buffer: wgpu::Buffer,
-    index_count: u32,
+
+    // If tiled, tiles come and go with the camera; otherwise there is one resident tile.
+    tiles: Vec<T2Tile>,
+    tiled: bool,
 
     // We need access to the height data for collisions, layout, etc.
     positions: HashMap<(u32, u32), Vector3<f32>>,
     normals: HashMap<[(u32, u32); 3], Vector3<f32>>,
     terrain: Terrain,
+
+    // Kept around so that we can export the terrain: the 5x5 texture coordinates of each
+    // patch, by its top-left sample.
+    tex_coords: HashMap<(u32, u32), [[f32; 2]; 25]>,
+    atlas_img: DynamicImage,
+
+    // Patches, by their top-left sample, that have no tmap. These get the [0, 0] texture
+    // coordinate, so they are drawn with only the colors of their samples.
+    untextured: HashSet<(u32, u32)>,
 }
 
 impl T2Buffer {
@@ -540,13 +719,71 @@ impl T2Buffer {
         gpu: &mut GPU,
     ) -> Fallible<Arc<RefCell<Self>>> {
         trace!("T2Renderer::new");
-        T2BufferFactory::new(mm, system_palette, catalog).build(gpu)
+        T2BufferFactory::new(mm, system_palette, catalog).build(None, gpu)
+    }
+
+    // Like new, but split the terrain into tiles of tile_size samples on a side and do not
+    // upload anything until update_tiles is called. Use this for very large terrains.
+    pub fn new_tiled(
+        mm: &MissionMap,
+        system_palette: &Palette,
+        catalog: &Catalog,
+        tile_size: u32,
+        gpu: &mut GPU,
+    ) -> Fallible<Arc<RefCell<Self>>> {
+        trace!("T2Renderer::new_tiled");
+        T2BufferFactory::new(mm, system_palette, catalog).build(Some(tile_size), gpu)
+    }
+
+    // Upload every tile with a center within radius_hm of the camera and release the rest.
+    // This does nothing if the terrain is not tiled.
+    pub fn update_tiles(&mut self, camera: &Point3<f32>, radius_hm: f32, gpu: &GPU) {
+        if !self.tiled {
+            return;
+        }
+        let wanted = self.tiles_near(camera, radius_hm);
+        for (tile, want) in self.tiles.iter_mut().zip(wanted) {
+            if want {
+                tile.upload(gpu);
+            } else {
+                tile.evict();
+            }
+        }
+    }
+
+    // Which tiles update_tiles would keep resident for a camera at the given position, in
+    // the same order as tiles().
+    pub fn tiles_near(&self, camera: &Point3<f32>, radius_hm: f32) -> Vec<bool> {
+        self.tiles
+            .iter()
+            .map(|tile| {
+                let center = self.positions[&(
+                    (tile.x_range.start + tile.x_range.end) / 2,
+                    (tile.z_range.start + tile.z_range.end) / 2,
+                )];
+                tile_in_radius([center[0], center[2]], camera, radius_hm)
+            })
+            .collect()
+    }
+
+    pub fn tiles(&self) -> &[T2Tile] {
+        &self.tiles
+    }
+
+    pub fn resident_tiles(&self) -> impl Iterator<Item = &T2Tile> {
+        self.tiles.iter().filter(|tile| tile.is_resident())
     }
 
     pub fn t2(&self) -> &Terrain {
         &self.terrain
     }
 
+    // True if the sample at xi, zi is in a patch without a tmap, which the game fills with
+    // the base color of each sample. Useful for highlighting holes in a mission's tiling.
+    pub fn uses_base_color_only(&self, xi: u32, zi: u32) -> bool {
+        self.untextured.contains(&(xi - xi % 4, zi - zi % 4))
+    }
+
     pub fn bind_group(&self) -> &wgpu::BindGroup {
         &self.bind_group
     }
@@ -555,16 +792,56 @@ impl T2Buffer {
         &self.bind_group_layout
     }
 
-    pub fn vertex_buffer(&self) -> &wgpu::Buffer {
-        &self.vertex_buffer
+    // Export the terrain as a single mesh textured with the atlas. Like the GPU buffers,
+    // each patch gets its own vertices, so that patches never blend into their neighbor's
+    // atlas frame along their shared edge.
+    pub fn to_gltf(&self) -> Fallible<gltf_json::Root> {
+        let mut patches = Vec::new();
+        for zi_base in (0..self.terrain.height()).step_by(4) {
+            for xi_base in (0..self.terrain.width()).step_by(4) {
+                if let Some(patch_tex_coords) = self.tex_coords.get(&(xi_base, zi_base)) {
+                    patches.push((xi_base, zi_base, *patch_tex_coords));
+                }
+            }
+        }
+        let (positions, tex_coords, indices) = patch_grid(&patches, |xi, zi| {
+            let p = self.positions[&(xi, zi)];
+            [p[0], p[1], p[2]]
+        });
+
+        build_textured_mesh(
+            self.terrain.name(),
+            &positions,
+            &tex_coords,
+            &indices,
+            &self.atlas_img,
+        )
     }
 
-    pub fn index_buffer(&self) -> &wgpu::Buffer {
-        &self.index_buffer
+    // The height we placed the sample at, in hectometers.
+    pub fn height_at(&self, xi: u32, zi: u32) -> f32 {
+        self.positions[&(xi, zi)][1]
     }
 
-    pub fn index_range(&self) -> Range<u32> {
-        0..self.index_count
+    // Move each vertex to the height of the nearest sample, on the CPU, for tools and tests
+    // that have no GPU. Vertices are in hectometers over the terrain, like the ones we
+    // build; any that fall off the edge take the height of the closest edge sample.
+    pub fn displace_height_cpu(&self, base_mesh: &[Vertex]) -> Vec<Vertex> {
+        let width = self.terrain.width();
+        let height = self.terrain.height();
+        let scale_x_hm = self.terrain.extent_east_west_in_ft() * FEET_TO_HM_32;
+        let scale_z_hm = self.terrain.extent_north_south_in_ft() * FEET_TO_HM_32;
+        let nearest = |f: f32, n: u32| (f * n as f32).round().max(0f32).min((n - 1) as f32) as u32;
+        base_mesh
+            .iter()
+            .map(|v| {
+                let xi = nearest(v.position[0] / scale_x_hm, width);
+                let zi = nearest(1f32 - v.position[2] / scale_z_hm, height);
+                let mut out = *v;
+                out.position[1] = self.height_at(xi, zi);
+                out
+            })
+            .collect()
     }
 
     #[allow(clippy::many_single_char_names)]
@@ -638,6 +915,135 @@ mod test {
     use lib::{from_dos_string, CatalogBuilder};
     use xt::TypeManager;
 
+    #[test]
+    fn it_can_lay_out_tiles() {
+        let tiles = tile_layout(1024, 1024, 256);
+        assert_eq!(tiles.len(), 16);
+        let mut covered = vec![0u8; 1024 * 1024];
+        for (x_range, z_range) in &tiles {
+            for zi in z_range.clone() {
+                for xi in x_range.clone() {
+                    covered[(zi * 1024 + xi) as usize] += 1;
+                }
+            }
+        }
+        assert!(covered.iter().all(|&c| c == 1));
+
+        // Edge tiles get clipped.
+        let tiles = tile_layout(1024, 1000, 256);
+        assert_eq!(tiles.len(), 16);
+        assert_eq!(tiles.last().unwrap().1, 768..1000);
+    }
+
+    #[test]
+    fn it_keeps_only_nearby_tiles() {
+        let camera = Point3::new(10f32, 0f32, 10f32);
+        assert!(tile_in_radius([10f32, 10f32], &camera, 0f32));
+        assert!(tile_in_radius([13f32, 14f32], &camera, 5f32));
+        assert!(!tile_in_radius([13f32, 14.1f32], &camera, 5f32));
+        // Height does not matter.
+        let above = Point3::new(10f32, 1000f32, 10f32);
+        assert!(tile_in_radius([13f32, 14f32], &above, 5f32));
+    }
+
+    #[test]
+    fn it_keeps_patch_uvs_in_their_own_frame() {
+        // Two patches side by side, in the left and right halves of the atlas.
+        let frame_coords = |u0: f32| {
+            let mut coords = [[0f32; 2]; 25];
+            for z_off in 0..5 {
+                for x_off in 0..5 {
+                    coords[z_off * 5 + x_off] = [u0 + x_off as f32 / 8f32, z_off as f32 / 4f32];
+                }
+            }
+            coords
+        };
+        let patches = vec![(0, 0, frame_coords(0f32)), (4, 0, frame_coords(0.5f32))];
+        let (positions, tex_coords, indices) =
+            patch_grid(&patches, |xi, zi| [xi as f32, 0f32, zi as f32]);
+        assert_eq!(positions.len(), 50);
+        assert_eq!(tex_coords.len(), 50);
+        assert_eq!(indices.len(), 2 * 16 * 6);
+
+        // The last quad of the first row of the first patch borders the second patch.
+        let quad = &indices[3 * 6..4 * 6];
+        for &i in quad {
+            let [u, _] = tex_coords[i as usize];
+            assert!((0f32..=0.5f32).contains(&u));
+        }
+        // Its far corners sit on the seam, at the same place as the next patch's first.
+        assert_eq!(positions[quad[2] as usize], positions[25]);
+        assert_eq!(tex_coords[quad[2] as usize], [0.5f32, 0f32]);
+
+        // And the next patch starts at its own frame's origin.
+        let quad = &indices[16 * 6..16 * 6 + 6];
+        for &i in quad {
+            let [u, _] = tex_coords[i as usize];
+            assert!((0.5f32..=1f32).contains(&u));
+        }
+    }
+
+    #[test]
+    fn it_can_page_tiles_around_the_camera() -> Fallible<()> {
+        let input = InputSystem::new(vec![])?;
+        let mut gpu = GPU::new(&input, Default::default())?;
+
+        let (mut catalog, inputs) =
+            CatalogBuilder::build_and_select(&["FA:PALETTE.PAL".to_owned()])?;
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let types = TypeManager::empty();
+            let palette = Palette::from_bytes(&catalog.read_name_sync("PALETTE.PAL")?)?;
+            let content = from_dos_string(catalog.read_name_sync("BAL.MM")?);
+            let mm = MissionMap::from_str(&content, &types, &catalog)?;
+            let t2_buffer = T2Buffer::new_tiled(&mm, &palette, &catalog, 64, &mut gpu)?;
+            let mut t2_buffer = t2_buffer.borrow_mut();
+
+            // The tiles cover every sample exactly once and start out on the CPU.
+            let (width, height) = (t2_buffer.t2().width(), t2_buffer.t2().height());
+            let expect = tile_layout(width, height, 64);
+            assert_eq!(t2_buffer.tiles().len(), expect.len());
+            let mut covered = 0;
+            for (tile, (x_range, z_range)) in t2_buffer.tiles().iter().zip(&expect) {
+                assert_eq!(&tile.x_range(), x_range);
+                assert_eq!(&tile.z_range(), z_range);
+                assert!(!tile.is_resident());
+                assert!(tile.index_range().end > 0);
+                covered += x_range.len() * z_range.len();
+            }
+            assert_eq!(covered, (width * height) as usize);
+
+            // Sit on the first tile: only the tiles near it come up.
+            let p = t2_buffer.positions[&(32, 32)];
+            let camera = Point3::new(p[0], 0f32, p[2]);
+            // About one tile across, so that only the neighbors of our own tile come up.
+            let radius_hm =
+                t2_buffer.t2().extent_east_west_in_ft() * FEET_TO_HM_32 * 64f32 / width as f32;
+            let near = t2_buffer.tiles_near(&camera, radius_hm);
+            assert!(near[0]);
+            assert!(!near[near.len() - 1]);
+            t2_buffer.update_tiles(&camera, radius_hm, &gpu);
+            for (tile, &want) in t2_buffer.tiles().iter().zip(&near) {
+                assert_eq!(tile.is_resident(), want);
+                assert_eq!(tile.vertex_buffer().is_some(), want);
+            }
+
+            // Moving to the far corner swaps them out.
+            let p = t2_buffer.positions[&(width - 1, height - 1)];
+            let camera = Point3::new(p[0], 0f32, p[2]);
+            t2_buffer.update_tiles(&camera, radius_hm, &gpu);
+            assert!(!t2_buffer.tiles()[0].is_resident());
+            assert!(t2_buffer.tiles().last().unwrap().is_resident());
+
+            // And nothing is kept with a radius of zero off the map.
+            let camera = Point3::new(-1_000_000f32, 0f32, -1_000_000f32);
+            t2_buffer.update_tiles(&camera, 0f32, &gpu);
+            assert_eq!(t2_buffer.resident_tiles().count(), 0);
+        }
+        Ok(())
+    }
+
     #[test]
     fn test_tile_to_earth() -> Fallible<()> {
         let input = InputSystem::new(vec![])?;
@@ -652,7 +1058,55 @@ mod test {
             let palette = Palette::from_bytes(&catalog.read_name_sync("PALETTE.PAL")?)?;
             let content = from_dos_string(catalog.read_name_sync("BAL.MM")?);
             let mm = MissionMap::from_str(&content, &types, &catalog)?;
-            let _t2_buffer = T2Buffer::new(&mm, &palette, &catalog, &mut gpu)?;
+            let t2_buffer = T2Buffer::new(&mm, &palette, &catalog, &mut gpu)?;
+
+            let t2_buffer = t2_buffer.borrow();
+            let gltf = t2_buffer.to_gltf()?;
+            let t2 = t2_buffer.t2();
+            let patches = ((t2.width() + 3) / 4) * ((t2.height() + 3) / 4);
+            assert_eq!(gltf.accessors[0].count, patches * 25);
+            assert_eq!(gltf.images.len(), 1);
+            assert_eq!(gltf.textures[0].source.value(), 0);
+            let material = &gltf.materials[0];
+            let info = material.pbr_metallic_roughness.base_color_texture.as_ref();
+            assert_eq!(info.map(|i| i.index.value()), Some(0));
+
+            let mut untextured = 0;
+            let mut blocks = 0;
+            for zi in (0..t2.height()).step_by(4) {
+                for xi in (0..t2.width()).step_by(4) {
+                    let expect = mm.texture_map(xi, zi).is_none();
+                    assert_eq!(t2_buffer.uses_base_color_only(xi, zi), expect);
+                    assert_eq!(t2_buffer.uses_base_color_only(xi + 3, zi + 3), expect);
+                    blocks += 1;
+                    if expect {
+                        untextured += 1;
+                    }
+                }
+            }
+            // BAL's tiles are all on the map, so every block without one is base color only.
+            assert!(mm.validate_layout(t2).is_empty());
+            assert_eq!(untextured, blocks - mm.texture_maps().count());
+
+            let scale_x_hm = t2.extent_east_west_in_ft() * FEET_TO_HM_32;
+            let scale_z_hm = t2.extent_north_south_in_ft() * FEET_TO_HM_32;
+            let mut grid = Vec::new();
+            let mut samples = Vec::new();
+            for zi in (0..t2.height()).step_by(8) {
+                for xi in (0..t2.width()).step_by(8) {
+                    let x = xi as f32 / t2.width() as f32 * scale_x_hm;
+                    let z = (1f32 - zi as f32 / t2.height() as f32) * scale_z_hm;
+                    grid.push(Vertex::at([x, 0f32, z]));
+                    samples.push((xi, zi));
+                }
+            }
+            let displaced = t2_buffer.displace_height_cpu(&grid);
+            assert_eq!(displaced.len(), grid.len());
+            for ((v, flat), &(xi, zi)) in displaced.iter().zip(&grid).zip(&samples) {
+                assert_eq!(v.position()[0], flat.position()[0]);
+                assert_eq!(v.position()[2], flat.position()[2]);
+                assert_eq!(v.position()[1], t2_buffer.height_at(xi, zi));
+            }
         }
         Ok(())
     }
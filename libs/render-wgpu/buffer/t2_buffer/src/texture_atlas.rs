@@ -12,7 +12,7 @@
 //
 // You should have received a copy of the GNU General Public License
 // along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
-use failure::{bail, ensure, Fallible};
+use failure::{bail, ensure, Fail, Fallible};
 use image::{DynamicImage, GenericImage, GenericImageView};
 use log::trace;
 use mm::{MapOrientation, TLoc};
@@ -60,6 +60,23 @@ impl Frame {
     }
 }
 
+#[derive(Debug, Fail)]
+pub enum AtlasError {
+    #[fail(display = "no frame in the atlas for {:?}", loc)]
+    UnknownFrame { loc: TLoc },
+    #[fail(
+        display = "a {}x{} image does not fit the {}x{} frame for {:?}; the atlas must be repacked",
+        width, height, frame_width, frame_height, loc
+    )]
+    NeedsRepack {
+        loc: TLoc,
+        width: u32,
+        height: u32,
+        frame_width: u32,
+        frame_height: u32,
+    },
+}
+
 // Size of a texture patch.
 const PATCH_SIZE: u32 = 256;
 const HALF_SIZE: u32 = 128;
@@ -84,6 +101,46 @@ impl TextureAtlas {
         }
     }
 
+    // Swap out the pixels of a single frame, e.g. when a tile is overridden at runtime. This
+    // only works if the new image is the same size as the old one: anything else fails with
+    // AtlasError::NeedsRepack and the caller will have to build a new atlas.
+    pub fn replace_frame(&mut self, loc: &TLoc, new_image: &DynamicImage) -> Fallible<()> {
+        let frame = self
+            .frames
+            .get(loc)
+            .ok_or_else(|| AtlasError::UnknownFrame {
+                loc: loc.to_owned(),
+            })?;
+        let (x0, y0, x1, y1) = self.frame_rect(frame);
+        if new_image.width() != x1 - x0 || new_image.height() != y1 - y0 {
+            return Err(AtlasError::NeedsRepack {
+                loc: loc.to_owned(),
+                width: new_image.width(),
+                height: new_image.height(),
+                frame_width: x1 - x0,
+                frame_height: y1 - y0,
+            }
+            .into());
+        }
+        ensure!(
+            self.img.copy_from(new_image, x0, y0),
+            "frame does not fit in the atlas"
+        );
+        Ok(())
+    }
+
+    // The pixels covered by the frame, as x0, y0, x1, y1, with the far edges exclusive.
+    fn frame_rect(&self, frame: &Frame) -> (u32, u32, u32, u32) {
+        let w = self.img.width() as f32;
+        let h = self.img.height() as f32;
+        (
+            (frame.coord0.s * w).round() as u32,
+            (frame.coord0.t * h).round() as u32,
+            (frame.coord1.s * w).round() as u32,
+            (frame.coord1.t * h).round() as u32,
+        )
+    }
+
     // Most terrains all use 256x256 images, so
     fn pack_trivial(sources: Vec<(TLoc, DynamicImage)>) -> Fallible<Self> {
         let num_across = (sources.len() as f64).sqrt().ceil() as u32;
@@ -207,6 +264,45 @@ mod test {
     // use pic::Pic;
     // use xt::TypeManager;
 
+    #[test]
+    fn it_can_replace_a_frame() -> Fallible<()> {
+        let solid = |size: u32, v: u8| {
+            DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+                size,
+                size,
+                image::Rgba([v, v, v, 255]),
+            ))
+        };
+        let sources = (0..4)
+            .map(|i| (TLoc::Index(i), solid(PATCH_SIZE, 10 * i as u8)))
+            .collect::<Vec<_>>();
+        let mut atlas = TextureAtlas::new(sources)?;
+        let before = atlas.img.to_rgba();
+
+        let loc = TLoc::Index(1);
+        atlas.replace_frame(&loc, &solid(PATCH_SIZE, 200))?;
+        let after = atlas.img.to_rgba();
+        let (x0, y0, x1, y1) = atlas.frame_rect(&atlas.frames[&loc]);
+        for (x, y, pixel) in after.enumerate_pixels() {
+            let inside = x >= x0 && x < x1 && y >= y0 && y < y1;
+            if inside {
+                assert_eq!(pixel, &image::Rgba([200, 200, 200, 255]));
+            } else {
+                assert_eq!(pixel, before.get_pixel(x, y));
+            }
+        }
+
+        let err = atlas.replace_frame(&loc, &solid(HALF_SIZE, 0)).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<AtlasError>(),
+            Some(AtlasError::NeedsRepack { .. })
+        ));
+        assert!(atlas
+            .replace_frame(&TLoc::Index(9), &solid(PATCH_SIZE, 0))
+            .is_err());
+        Ok(())
+    }
+
     #[test]
     fn test_t2_texture_atlas() -> Fallible<()> {
         // TODO: we need to figure out the real approach here.
@@ -22,6 +22,12 @@ use t2_buffer::{T2Buffer, T2Vertex};
 
 pub struct T2TerrainRenderPass {
     pipeline: wgpu::RenderPipeline,
+
+    // Debug view of the tessellation. wgpu does not give us a line polygon mode yet, so
+    // this re-walks the terrain strips as line strips, which draws the rungs and the
+    // diagonals of each strip.
+    wireframe_pipeline: wgpu::RenderPipeline,
+    wireframe: bool,
 }
 
 impl T2TerrainRenderPass {
@@ -48,16 +54,44 @@ impl T2TerrainRenderPass {
                     ],
                 });
 
-        let pipeline = gpu
-            .device()
+        let pipeline = Self::build_pipeline(
+            gpu,
+            &pipeline_layout,
+            &vert_shader,
+            &frag_shader,
+            wgpu::PrimitiveTopology::TriangleStrip,
+        );
+        let wireframe_pipeline = Self::build_pipeline(
+            gpu,
+            &pipeline_layout,
+            &vert_shader,
+            &frag_shader,
+            wgpu::PrimitiveTopology::LineStrip,
+        );
+
+        Ok(Self {
+            pipeline,
+            wireframe_pipeline,
+            wireframe: false,
+        })
+    }
+
+    fn build_pipeline(
+        gpu: &GPU,
+        pipeline_layout: &wgpu::PipelineLayout,
+        vert_shader: &wgpu::ShaderModule,
+        frag_shader: &wgpu::ShaderModule,
+        primitive_topology: wgpu::PrimitiveTopology,
+    ) -> wgpu::RenderPipeline {
+        gpu.device()
             .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                layout: &pipeline_layout,
+                layout: pipeline_layout,
                 vertex_stage: wgpu::ProgrammableStageDescriptor {
-                    module: &vert_shader,
+                    module: vert_shader,
                     entry_point: "main",
                 },
                 fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
-                    module: &frag_shader,
+                    module: frag_shader,
                     entry_point: "main",
                 }),
                 rasterization_state: Some(wgpu::RasterizationStateDescriptor {
@@ -67,7 +101,7 @@ impl T2TerrainRenderPass {
                     depth_bias_slope_scale: 0.0,
                     depth_bias_clamp: 0.0,
                 }),
-                primitive_topology: wgpu::PrimitiveTopology::TriangleStrip,
+                primitive_topology,
                 color_states: &[wgpu::ColorStateDescriptor {
                     format: GPU::texture_format(),
                     color_blend: wgpu::BlendDescriptor {
@@ -94,9 +128,23 @@ impl T2TerrainRenderPass {
                 sample_count: 1,
                 sample_mask: !0,
                 alpha_to_coverage_enabled: false,
-            });
+            })
+    }
 
-        Ok(Self { pipeline })
+    pub fn set_wireframe(&mut self, wireframe: bool) {
+        self.wireframe = wireframe;
+    }
+
+    pub fn is_wireframe(&self) -> bool {
+        self.wireframe
+    }
+
+    fn active_pipeline(&self) -> &wgpu::RenderPipeline {
+        if self.wireframe {
+            &self.wireframe_pipeline
+        } else {
+            &self.pipeline
+        }
     }
 
     pub fn draw<'a>(
@@ -106,7 +154,7 @@ impl T2TerrainRenderPass {
         atmosphere_buffer: &'a AtmosphereBuffer,
         t2_buffer: &'a T2Buffer,
     ) -> wgpu::RenderPass<'a> {
-        rpass.set_pipeline(&self.pipeline);
+        rpass.set_pipeline(self.active_pipeline());
         rpass.set_bind_group(Group::Globals.index(), &globals_buffer.bind_group(), &[]);
         rpass.set_bind_group(
             Group::Atmosphere.index(),
@@ -114,9 +162,64 @@ impl T2TerrainRenderPass {
             &[],
         );
         rpass.set_bind_group(Group::Terrain.index(), &t2_buffer.bind_group(), &[]);
-        rpass.set_index_buffer(t2_buffer.index_buffer(), 0, 0);
-        rpass.set_vertex_buffer(0, &t2_buffer.vertex_buffer(), 0, 0);
-        rpass.draw_indexed(t2_buffer.index_range(), 0, 0..1);
+        for tile in t2_buffer.resident_tiles() {
+            if let (Some(index_buffer), Some(vertex_buffer)) =
+                (tile.index_buffer(), tile.vertex_buffer())
+            {
+                rpass.set_index_buffer(index_buffer, 0, 0);
+                rpass.set_vertex_buffer(0, vertex_buffer, 0, 0);
+                rpass.draw_indexed(tile.index_range(), 0, 0..1);
+            }
+        }
         rpass
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use input::InputSystem;
+    use lib::{from_dos_string, CatalogBuilder};
+    use mm::MissionMap;
+    use pal::Palette;
+    use xt::TypeManager;
+
+    #[test]
+    fn it_can_toggle_wireframe() -> Fallible<()> {
+        let input = InputSystem::new(vec![])?;
+        let mut gpu = GPU::new(&input, Default::default())?;
+        let atmosphere_buffer = AtmosphereBuffer::new(&mut gpu)?;
+        let globals_buffer = GlobalParametersBuffer::new(gpu.device())?;
+
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["FA:BAL.MM".to_owned()])?;
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let types = TypeManager::empty();
+            let palette = Palette::from_bytes(&catalog.read_name_sync("PALETTE.PAL")?)?;
+            let mm =
+                MissionMap::from_str(&from_dos_string(catalog.read_sync(fid)?), &types, &catalog)?;
+            let t2_buffer = T2Buffer::new(&mm, &palette, &catalog, &mut gpu)?;
+
+            let mut pass = T2TerrainRenderPass::new(
+                &mut gpu,
+                &globals_buffer.borrow(),
+                &atmosphere_buffer.borrow(),
+                &t2_buffer.borrow(),
+            )?;
+            assert!(!pass.is_wireframe());
+            assert!(std::ptr::eq(pass.active_pipeline(), &pass.pipeline));
+
+            pass.set_wireframe(true);
+            assert!(pass.is_wireframe());
+            assert!(std::ptr::eq(
+                pass.active_pipeline(),
+                &pass.wireframe_pipeline
+            ));
+
+            pass.set_wireframe(false);
+            assert!(std::ptr::eq(pass.active_pipeline(), &pass.pipeline));
+        }
+        Ok(())
+    }
+}
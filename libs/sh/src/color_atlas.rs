@@ -0,0 +1,113 @@
+// This file is part of OpenFA.
+//
+// OpenFA is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// OpenFA is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
+use crate::mesh::MeshData;
+use image::{Rgba, RgbaImage};
+use pal::Palette;
+
+// Each color gets a solid square, so that filtering and mipmapping near the center of the
+// square never picks up its neighbors.
+const CELL_SIZE: u32 = 4;
+
+impl MeshData {
+    // Paint every palette color used by an untextured face into a small atlas, and give each
+    // face a uv at the center of its color's square. This lets an engine that wants a single
+    // material draw flat colored faces from a texture. Textured faces are given the uv of
+    // their color too, but should keep using their own texture coordinates.
+    pub fn bake_vertex_colors_to_texture(&self, palette: &Palette) -> (RgbaImage, Vec<[f32; 2]>) {
+        let mut colors = Vec::new();
+        for face in &self.faces {
+            if face.texture.is_none() && !colors.contains(&face.color) {
+                colors.push(face.color);
+            }
+        }
+        for face in &self.faces {
+            if !colors.contains(&face.color) {
+                colors.push(face.color);
+            }
+        }
+
+        let columns = (colors.len() as f32).sqrt().ceil().max(1f32) as u32;
+        let rows = ((colors.len() as u32 + columns - 1) / columns).max(1);
+        let mut img = RgbaImage::new(columns * CELL_SIZE, rows * CELL_SIZE);
+        for (i, &color) in colors.iter().enumerate() {
+            let (cx, cy) = (i as u32 % columns, i as u32 / columns);
+            let pixel = palette.rgba(color as usize).unwrap_or(Rgba {
+                data: [255, 0, 255, 255],
+            });
+            for y in 0..CELL_SIZE {
+                for x in 0..CELL_SIZE {
+                    img.put_pixel(cx * CELL_SIZE + x, cy * CELL_SIZE + y, pixel);
+                }
+            }
+        }
+
+        let (width, height) = img.dimensions();
+        let uvs = self
+            .faces
+            .iter()
+            .map(|face| {
+                let i = colors.iter().position(|&c| c == face.color).unwrap_or(0) as u32;
+                let (cx, cy) = (i % columns, i / columns);
+                [
+                    ((cx * CELL_SIZE) as f32 + CELL_SIZE as f32 / 2f32) / width as f32,
+                    ((cy * CELL_SIZE) as f32 + CELL_SIZE as f32 / 2f32) / height as f32,
+                ]
+            })
+            .collect();
+        (img, uvs)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{FacetFlags, MeshFace};
+
+    #[test]
+    fn it_can_bake_face_colors() {
+        let positions = vec![[0f32, 0f32, 0f32], [1f32, 0f32, 0f32], [0f32, 1f32, 0f32]];
+        let mut faces = Vec::new();
+        for &(color, texture) in &[(3u8, None), (7, None), (3, None), (9, Some("A.PIC"))] {
+            faces.push(MeshFace {
+                indices: vec![0, 1, 2],
+                tex_coords: Vec::new(),
+                texture: texture.map(str::to_owned),
+                color,
+                flags: FacetFlags::empty(),
+                normal: [0f32, 0f32, 1f32],
+                material_normal: None,
+            });
+        }
+        let mesh = MeshData::new(positions, faces);
+        let palette = Palette::grayscale().unwrap();
+        let (img, uvs) = mesh.bake_vertex_colors_to_texture(&palette);
+
+        assert_eq!(uvs.len(), mesh.faces.len());
+        assert_eq!(uvs[0], uvs[2]);
+        assert_ne!(uvs[0], uvs[1]);
+        let (width, height) = img.dimensions();
+        for (face, uv) in mesh.faces.iter().zip(&uvs) {
+            if face.texture.is_some() {
+                continue;
+            }
+            let x = (uv[0] * width as f32) as u32;
+            let y = (uv[1] * height as f32) as u32;
+            assert_eq!(
+                *img.get_pixel(x, y),
+                palette.rgba(face.color as usize).unwrap()
+            );
+        }
+    }
+}
@@ -0,0 +1,83 @@
+// This file is part of OpenFA.
+//
+// OpenFA is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// OpenFA is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
+use crate::RawShape;
+use catalog::Catalog;
+use failure::Fallible;
+use pic::Pic;
+use std::collections::HashSet;
+
+// How much texture a shape needs once its PICs are decoded.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct TextureFootprint {
+    pub textures: usize,
+    pub pixels: u64,
+}
+
+impl TextureFootprint {
+    // Size of the decoded textures as 8 bit RGBA, which is what we upload.
+    pub fn rgba_bytes(&self) -> u64 {
+        self.pixels * 4
+    }
+}
+
+impl RawShape {
+    // Sum the dimensions of every texture the shape references. Each PIC is only counted
+    // once, however many times it is referenced.
+    pub fn texture_footprint(&self, catalog: &Catalog) -> Fallible<TextureFootprint> {
+        let names = self
+            .all_textures()
+            .iter()
+            .map(|name| name.to_uppercase())
+            .collect::<HashSet<_>>();
+        let mut footprint = TextureFootprint::default();
+        for name in &names {
+            let pic = Pic::from_bytes(&catalog.read_name_sync(name)?)?;
+            footprint.textures += 1;
+            footprint.pixels += u64::from(pic.width) * u64::from(pic.height);
+        }
+        Ok(footprint)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use lib::CatalogBuilder;
+
+    #[test]
+    fn it_can_compute_a_texture_footprint() -> Fallible<()> {
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["FA:F18.SH".to_owned()])?;
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let shape = RawShape::from_bytes(&catalog.read_sync(fid)?)?;
+            let footprint = shape.texture_footprint(&catalog)?;
+
+            let mut expect = 0u64;
+            let mut seen = HashSet::new();
+            for name in shape.all_textures() {
+                if seen.insert(name.to_uppercase()) {
+                    let pic = Pic::from_bytes(&catalog.read_name_sync(&name.to_uppercase())?)?;
+                    expect += u64::from(pic.width * pic.height);
+                }
+            }
+            assert!(footprint.textures > 0);
+            assert_eq!(footprint.textures, seen.len());
+            assert_eq!(footprint.pixels, expect);
+            assert_eq!(footprint.rgba_bytes(), expect * 4);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,763 @@
+// This file is part of OpenFA.
+//
+// OpenFA is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// OpenFA is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
+use crate::{
+    instr::RenderHints,
+    mesh::{build_mesh, CoordinateConvention, MeshData, MeshOptions},
+    Facet, Instr, RawShape,
+};
+use catalog::Catalog;
+use failure::Fallible;
+use gltf_json::{
+    accessor::{ComponentType, GenericComponentType, Type},
+    animation::{Channel, Interpolation, Property, Sampler, Target},
+    buffer::View,
+    image::MimeType,
+    material::{AlphaMode, PbrBaseColorFactor, PbrMetallicRoughness},
+    mesh::{Mode, Primitive, Semantic},
+    scene::{Node, UnitQuaternion},
+    texture::Info,
+    validation::Checked::Valid,
+    Accessor, Animation, Asset, Buffer, Image, Index, Material, Mesh, Root, Scene, Texture, Value,
+};
+use image::{GenericImageView, ImageOutputFormat};
+use pal::Palette;
+use pic::Pic;
+use std::collections::{BTreeMap, HashMap};
+
+impl RenderHints {
+    // Build a glTF material for facets with these hints. Textured facets that do not fill
+    // the background have their transparent texels cut out.
+    pub fn gltf_material(&self, texture: Option<u32>) -> Material {
+        let alpha_mode = if texture.is_some() && !self.fill_background {
+            AlphaMode::Mask
+        } else {
+            AlphaMode::Opaque
+        };
+        Material {
+            alpha_mode: Valid(alpha_mode),
+            double_sided: self.double_sided,
+            pbr_metallic_roughness: PbrMetallicRoughness {
+                base_color_texture: texture.map(|index| Info {
+                    index: Index::new(index),
+                    tex_coord: 0,
+                    extensions: None,
+                    extras: Default::default(),
+                }),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+}
+
+// Accumulates the buffer, views, and accessors for a self-contained glTF document.
+#[derive(Default)]
+struct GltfBuffer {
+    bytes: Vec<u8>,
+    views: Vec<View>,
+    accessors: Vec<Accessor>,
+}
+
+impl GltfBuffer {
+    fn push(
+        &mut self,
+        data: &[u8],
+        count: usize,
+        component_type: ComponentType,
+        type_: Type,
+        bounds: Option<(Value, Value)>,
+    ) -> Index<Accessor> {
+        let view = View {
+            buffer: Index::new(0),
+            byte_length: data.len() as u32,
+            byte_offset: Some(self.bytes.len() as u32),
+            byte_stride: None,
+            name: None,
+            target: None,
+            extensions: None,
+            extras: Default::default(),
+        };
+        self.bytes.extend_from_slice(data);
+        let (min, max) = match bounds {
+            Some((min, max)) => (Some(min), Some(max)),
+            None => (None, None),
+        };
+        self.accessors.push(Accessor {
+            buffer_view: Some(Index::new(self.views.len() as u32)),
+            byte_offset: 0,
+            count: count as u32,
+            component_type: Valid(GenericComponentType(component_type)),
+            extensions: None,
+            extras: Default::default(),
+            type_: Valid(type_),
+            min,
+            max,
+            name: None,
+            normalized: false,
+            sparse: None,
+        });
+        self.views.push(view);
+        Index::new(self.accessors.len() as u32 - 1)
+    }
+
+    fn push_f32s(&mut self, values: &[f32], type_: Type, count: usize) -> Index<Accessor> {
+        let data = values
+            .iter()
+            .flat_map(|v| v.to_le_bytes().to_vec())
+            .collect::<Vec<u8>>();
+        self.push(&data, count, ComponentType::F32, type_, None)
+    }
+
+    // glTF requires bounds on position accessors.
+    fn push_positions(&mut self, positions: &[[f32; 3]]) -> Index<Accessor> {
+        let mut lo = [std::f32::MAX; 3];
+        let mut hi = [std::f32::MIN; 3];
+        for p in positions {
+            for i in 0..3 {
+                lo[i] = lo[i].min(p[i]);
+                hi[i] = hi[i].max(p[i]);
+            }
+        }
+        let flat = positions.iter().flatten().cloned().collect::<Vec<f32>>();
+        let accessor = self.push_f32s(&flat, Type::Vec3, positions.len());
+        let bounds = &mut self.accessors[accessor.value()];
+        bounds.min = Some(Value::from(lo.to_vec()));
+        bounds.max = Some(Value::from(hi.to_vec()));
+        accessor
+    }
+
+    fn push_u32s(&mut self, values: &[u32]) -> Index<Accessor> {
+        let data = values
+            .iter()
+            .flat_map(|v| v.to_le_bytes().to_vec())
+            .collect::<Vec<u8>>();
+        self.push(&data, values.len(), ComponentType::U32, Type::Scalar, None)
+    }
+
+    // Embed the data in a uri, so that the document can be written out as a single file.
+    fn finish(self) -> (Buffer, Vec<View>, Vec<Accessor>) {
+        let buffer = Buffer {
+            byte_length: self.bytes.len() as u32,
+            name: None,
+            uri: Some(format!(
+                "data:application/octet-stream;base64,{}",
+                base64::encode(&self.bytes)
+            )),
+            extensions: None,
+            extras: Default::default(),
+        };
+        (buffer, self.views, self.accessors)
+    }
+}
+
+// Export the frames of every JumpToFrame in the shape as a glTF document. Each frame's facet
+// becomes its own node, and each JumpToFrame becomes a stepped animation that shows one
+// frame at a time by scaling the others to nothing, since glTF has no visibility channel.
+// Frames that swap textures get a material named after the texture they swap in.
+pub fn build_frame_animation(sh: &RawShape, seconds_per_frame: f32) -> Fallible<Root> {
+    let positions = build_mesh(sh, &MeshOptions::default())?.positions;
+
+    let mut textures = HashMap::new();
+    let mut texture = None;
+    for instr in &sh.instrs {
+        match instr {
+            Instr::TextureRef(tex) => texture = Some(tex.filename.to_uppercase()),
+            Instr::Facet(facet) => {
+                textures.insert(facet.offset, texture.clone());
+            }
+            _ => {}
+        }
+    }
+
+    let mut buffer = GltfBuffer::default();
+    let position_accessor = buffer.push_positions(&positions);
+
+    let mut materials = Vec::new();
+    let mut meshes = Vec::new();
+    let mut nodes = Vec::new();
+    let mut animations = Vec::new();
+    for instr in &sh.instrs {
+        let frames = match instr {
+            Instr::JumpToFrame(frames) => frames,
+            _ => continue,
+        };
+
+        let mut frame_nodes = Vec::new();
+        for n in 0..frames.num_frames() {
+            let index = sh.bytes_to_index(frames.target_for_frame(n))?;
+            let facet = match &sh.instrs[index] {
+                Instr::Facet(facet) => facet,
+                _ => continue,
+            };
+            let texture = textures.get(&facet.offset).cloned().flatten();
+            let (mut primitive, material) =
+                match frame_primitive(facet, &positions, position_accessor, &mut buffer) {
+                    Some(parts) => parts,
+                    None => continue,
+                };
+            materials.push(Material {
+                name: texture,
+                ..material
+            });
+            primitive.material = Some(Index::new(materials.len() as u32 - 1));
+            meshes.push(Mesh {
+                extensions: None,
+                extras: Default::default(),
+                name: None,
+                primitives: vec![primitive],
+                weights: None,
+            });
+            nodes.push(Node {
+                camera: None,
+                children: None,
+                extensions: None,
+                extras: Default::default(),
+                matrix: None,
+                mesh: Some(Index::new(meshes.len() as u32 - 1)),
+                name: Some(format!("frame{}@{:04X}", n, frames.offset)),
+                rotation: None,
+                scale: None,
+                translation: None,
+                skin: None,
+                weights: None,
+            });
+            frame_nodes.push(nodes.len() as u32 - 1);
+        }
+        if frame_nodes.is_empty() {
+            continue;
+        }
+
+        let count = frame_nodes.len();
+        let times = (0..count)
+            .map(|k| k as f32 * seconds_per_frame)
+            .collect::<Vec<_>>();
+        let input = buffer.push_f32s(&times, Type::Scalar, count);
+        let mut samplers = Vec::new();
+        let mut channels = Vec::new();
+        for (i, &node) in frame_nodes.iter().enumerate() {
+            let scales = (0..count)
+                .flat_map(|k| {
+                    let s = if k == i { 1f32 } else { 0f32 };
+                    vec![s, s, s]
+                })
+                .collect::<Vec<_>>();
+            let output = buffer.push_f32s(&scales, Type::Vec3, count);
+            samplers.push(Sampler {
+                extensions: None,
+                extras: Default::default(),
+                input,
+                interpolation: Valid(Interpolation::Step),
+                output,
+            });
+            channels.push(Channel {
+                sampler: Index::new(samplers.len() as u32 - 1),
+                target: Target {
+                    extensions: None,
+                    extras: Default::default(),
+                    node: Index::new(node),
+                    path: Valid(Property::Scale),
+                },
+                extensions: None,
+                extras: Default::default(),
+            });
+        }
+        animations.push(Animation {
+            extensions: None,
+            extras: Default::default(),
+            channels,
+            name: Some(format!("JumpToFrame@{:04X}", frames.offset)),
+            samplers,
+        });
+    }
+
+    let scene_nodes = (0..nodes.len() as u32).map(Index::new).collect();
+    let (buffer, buffer_views, accessors) = buffer.finish();
+    Ok(Root {
+        accessors,
+        animations,
+        asset: Asset {
+            generator: Some("OpenFA".to_owned()),
+            ..Default::default()
+        },
+        buffer_views,
+        buffers: vec![buffer],
+        materials,
+        meshes,
+        nodes,
+        scenes: vec![Scene {
+            extensions: None,
+            extras: Default::default(),
+            name: None,
+            nodes: scene_nodes,
+        }],
+        scene: Some(Index::new(0)),
+        ..Default::default()
+    })
+}
+
+// Fan the facet out into triangles and push its indices. Facets that reach outside of the
+// vertex pool are skipped.
+fn frame_primitive(
+    facet: &Facet,
+    positions: &[[f32; 3]],
+    position_accessor: Index<Accessor>,
+    buffer: &mut GltfBuffer,
+) -> Option<(Primitive, Material)> {
+    if facet.indices.len() < 3
+        || facet
+            .indices
+            .iter()
+            .any(|&i| usize::from(i) >= positions.len())
+    {
+        return None;
+    }
+    let mut indices = Vec::new();
+    for pair in facet.indices[1..].windows(2) {
+        indices.extend_from_slice(&[
+            u32::from(facet.indices[0]),
+            u32::from(pair[0]),
+            u32::from(pair[1]),
+        ]);
+    }
+    let index_accessor = buffer.push_u32s(&indices);
+
+    let mut attributes = HashMap::new();
+    attributes.insert(Valid(Semantic::Positions), position_accessor);
+    let material = facet.render_hints().gltf_material(None);
+    Some((
+        Primitive {
+            attributes,
+            extensions: None,
+            extras: Default::default(),
+            indices: Some(index_accessor),
+            material: None,
+            mode: Valid(Mode::Triangles),
+            targets: None,
+        },
+        material,
+    ))
+}
+
+impl RawShape {
+    // Export the normal, highest detail, model as a glTF document in glTF's y up axes. Faces
+    // are wound to agree with their material normals, since glTF culls by winding. Textured
+    // faces get their PIC, decoded with the palette and embedded as a PNG; everything else is
+    // flat colored from the palette.
+    pub fn export_gltf(&self, catalog: &Catalog, palette: &Palette) -> Fallible<Root> {
+        let options = MeshOptions {
+            fix_winding: true,
+            split_uv_seams: true,
+            convention: CoordinateConvention::YUp,
+            ..Default::default()
+        };
+        let mesh = build_mesh(self, &options)?;
+        let name = self
+            .instrs
+            .iter()
+            .find_map(|instr| match instr {
+                Instr::SourceRef(source) => Some(source.source.clone()),
+                _ => None,
+            })
+            .unwrap_or_else(|| "shape".to_owned());
+        let mut scene = SceneBuilder::new();
+        let index = scene.add_textured_mesh(&name, &mesh, catalog, palette)?;
+        scene.add_node(&name, Some(index), [0f32; 3], [0f32, 0f32, 0f32, 1f32]);
+        Ok(scene.finish())
+    }
+}
+
+// What a primitive is drawn with: a flat palette color, or a texture with the render hints
+// of the faces that use it.
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+enum MaterialKey {
+    Color(u8),
+    Texture(String, bool, bool),
+}
+
+// Collects meshes and placed instances of them into a single glTF scene. Faces are colored
+// from the palette, unless the mesh is added with add_textured_mesh.
+#[derive(Default)]
+pub struct SceneBuilder {
+    buffer: GltfBuffer,
+    materials: Vec<Material>,
+    color_materials: HashMap<u8, u32>,
+    images: Vec<Image>,
+    textures: Vec<Texture>,
+    // The texture index and size for each PIC that we have embedded.
+    pic_textures: HashMap<String, (u32, [f32; 2])>,
+    texture_materials: HashMap<MaterialKey, u32>,
+    meshes: Vec<Mesh>,
+    nodes: Vec<Node>,
+}
+
+impl SceneBuilder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    // Add the mesh, with one primitive per palette color, and return the index to give to
+    // add_node. Faces that reach outside of the vertex pool are skipped.
+    pub fn add_mesh(&mut self, name: &str, mesh: &MeshData, palette: &Palette) -> Fallible<u32> {
+        self.add_mesh_with_textures(name, mesh, None, palette)
+    }
+
+    // As add_mesh, but textured faces get a primitive per texture, with the PIC loaded from
+    // the catalog. This needs a mesh with split uv seams, so that every vertex has one uv.
+    pub fn add_textured_mesh(
+        &mut self,
+        name: &str,
+        mesh: &MeshData,
+        catalog: &Catalog,
+        palette: &Palette,
+    ) -> Fallible<u32> {
+        self.add_mesh_with_textures(name, mesh, Some(catalog), palette)
+    }
+
+    fn add_mesh_with_textures(
+        &mut self,
+        name: &str,
+        mesh: &MeshData,
+        catalog: Option<&Catalog>,
+        palette: &Palette,
+    ) -> Fallible<u32> {
+        let position_accessor = self.buffer.push_positions(&mesh.positions);
+        // glTF requires normals wherever there are tangents.
+        let tangent_accessors = if !mesh.positions.is_empty()
+            && mesh.tangents.len() == mesh.positions.len()
+            && mesh.normals.len() == mesh.positions.len()
+        {
+            let normals = mesh.normals.iter().flatten().cloned().collect::<Vec<f32>>();
+            let tangents = mesh
+                .tangents
+                .iter()
+                .flatten()
+                .cloned()
+                .collect::<Vec<f32>>();
+            Some((
+                self.buffer
+                    .push_f32s(&normals, Type::Vec3, mesh.normals.len()),
+                self.buffer
+                    .push_f32s(&tangents, Type::Vec4, mesh.tangents.len()),
+            ))
+        } else {
+            None
+        };
+        let have_uvs = mesh.tex_coords.len() == mesh.positions.len();
+        let mut by_material = BTreeMap::new();
+        for face in &mesh.faces {
+            if face.indices.len() < 3
+                || face
+                    .indices
+                    .iter()
+                    .any(|&i| i as usize >= mesh.positions.len())
+            {
+                continue;
+            }
+            let key = match (&face.texture, catalog) {
+                (Some(texture), Some(_)) if have_uvs => {
+                    let hints = face.flags.render_hints();
+                    MaterialKey::Texture(
+                        texture.to_owned(),
+                        hints.fill_background,
+                        hints.double_sided,
+                    )
+                }
+                _ => MaterialKey::Color(face.color),
+            };
+            let indices = by_material.entry(key).or_insert_with(Vec::new);
+            for pair in face.indices[1..].windows(2) {
+                let tri = [face.indices[0], pair[0], pair[1]];
+                // Facets sometimes repeat a vertex, which would leave a zero area triangle.
+                if tri[0] != tri[1] && tri[1] != tri[2] && tri[0] != tri[2] {
+                    indices.extend_from_slice(&tri);
+                }
+            }
+        }
+
+        let mut primitives = Vec::new();
+        for (key, indices) in by_material {
+            if indices.is_empty() {
+                continue;
+            }
+            let index_accessor = self.buffer.push_u32s(&indices);
+            let mut attributes = HashMap::new();
+            attributes.insert(Valid(Semantic::Positions), position_accessor);
+            if let Some((normal_accessor, tangent_accessor)) = tangent_accessors {
+                attributes.insert(Valid(Semantic::Normals), normal_accessor);
+                attributes.insert(Valid(Semantic::Tangents), tangent_accessor);
+            }
+            let material = match (&key, catalog) {
+                (MaterialKey::Texture(texture, _, _), Some(catalog)) => {
+                    let (_, size) = self.pic_texture(texture, catalog, palette)?;
+                    let uvs = mesh
+                        .tex_coords
+                        .iter()
+                        .flat_map(|uv| {
+                            let [u, v] = uv.unwrap_or([0, 0]);
+                            vec![f32::from(u) / size[0], f32::from(v) / size[1]]
+                        })
+                        .collect::<Vec<f32>>();
+                    let uv_accessor = self
+                        .buffer
+                        .push_f32s(&uvs, Type::Vec2, mesh.positions.len());
+                    attributes.insert(Valid(Semantic::TexCoords(0)), uv_accessor);
+                    self.texture_material(&key, catalog, palette)?
+                }
+                (MaterialKey::Color(color), _) => self.color_material(*color, palette)?,
+                _ => unreachable!(),
+            };
+            primitives.push(Primitive {
+                attributes,
+                extensions: None,
+                extras: Default::default(),
+                indices: Some(index_accessor),
+                material: Some(Index::new(material)),
+                mode: Valid(Mode::Triangles),
+                targets: None,
+            });
+        }
+        self.meshes.push(Mesh {
+            extensions: None,
+            extras: Default::default(),
+            name: Some(name.to_owned()),
+            primitives,
+            weights: None,
+        });
+        Ok(self.meshes.len() as u32 - 1)
+    }
+
+    // Place a mesh in the scene. Nodes without a mesh are kept so that every instance the
+    // caller adds shows up, even when it has nothing to draw.
+    pub fn add_node(
+        &mut self,
+        name: &str,
+        mesh: Option<u32>,
+        translation: [f32; 3],
+        rotation: [f32; 4],
+    ) -> u32 {
+        self.nodes.push(Node {
+            camera: None,
+            children: None,
+            extensions: None,
+            extras: Default::default(),
+            matrix: None,
+            mesh: mesh.map(Index::new),
+            name: Some(name.to_owned()),
+            rotation: Some(UnitQuaternion(rotation)),
+            scale: None,
+            translation: Some(translation),
+            skin: None,
+            weights: None,
+        });
+        self.nodes.len() as u32 - 1
+    }
+
+    pub fn finish(self) -> Root {
+        let scene_nodes = (0..self.nodes.len() as u32).map(Index::new).collect();
+        let (buffer, buffer_views, accessors) = self.buffer.finish();
+        Root {
+            accessors,
+            asset: Asset {
+                generator: Some("OpenFA".to_owned()),
+                ..Default::default()
+            },
+            buffer_views,
+            buffers: vec![buffer],
+            images: self.images,
+            materials: self.materials,
+            meshes: self.meshes,
+            nodes: self.nodes,
+            scenes: vec![Scene {
+                extensions: None,
+                extras: Default::default(),
+                name: None,
+                nodes: scene_nodes,
+            }],
+            scene: Some(Index::new(0)),
+            textures: self.textures,
+            ..Default::default()
+        }
+    }
+
+    fn color_material(&mut self, color: u8, palette: &Palette) -> Fallible<u32> {
+        if let Some(&index) = self.color_materials.get(&color) {
+            return Ok(index);
+        }
+        self.materials.push(Material {
+            name: Some(format!("color{}", color)),
+            pbr_metallic_roughness: PbrMetallicRoughness {
+                base_color_factor: PbrBaseColorFactor(palette.rgba_f32(color as usize)?),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let index = self.materials.len() as u32 - 1;
+        self.color_materials.insert(color, index);
+        Ok(index)
+    }
+
+    fn texture_material(
+        &mut self,
+        key: &MaterialKey,
+        catalog: &Catalog,
+        palette: &Palette,
+    ) -> Fallible<u32> {
+        if let Some(&index) = self.texture_materials.get(key) {
+            return Ok(index);
+        }
+        let (name, hints) = match key {
+            MaterialKey::Texture(name, fill_background, double_sided) => (
+                name,
+                RenderHints {
+                    fill_background: *fill_background,
+                    double_sided: *double_sided,
+                },
+            ),
+            MaterialKey::Color(_) => unreachable!(),
+        };
+        let (texture, _) = self.pic_texture(name, catalog, palette)?;
+        self.materials.push(Material {
+            name: Some(name.to_owned()),
+            ..hints.gltf_material(Some(texture))
+        });
+        let index = self.materials.len() as u32 - 1;
+        self.texture_materials.insert(key.to_owned(), index);
+        Ok(index)
+    }
+
+    // Decode and embed a PIC the first time it is used; return the texture and its size.
+    fn pic_texture(
+        &mut self,
+        name: &str,
+        catalog: &Catalog,
+        palette: &Palette,
+    ) -> Fallible<(u32, [f32; 2])> {
+        if let Some(&found) = self.pic_textures.get(name) {
+            return Ok(found);
+        }
+        let img = Pic::decode(palette, &catalog.read_name_sync(name)?)?;
+        let mut png = Vec::new();
+        img.write_to(&mut png, ImageOutputFormat::PNG)?;
+        self.images.push(Image {
+            buffer_view: None,
+            mime_type: Some(MimeType("image/png".to_owned())),
+            name: Some(name.to_owned()),
+            uri: Some(format!("data:image/png;base64,{}", base64::encode(&png))),
+            extensions: None,
+            extras: Default::default(),
+        });
+        self.textures.push(Texture {
+            name: Some(name.to_owned()),
+            sampler: None,
+            source: Index::new(self.images.len() as u32 - 1),
+            extensions: None,
+            extras: Default::default(),
+        });
+        let found = (
+            self.textures.len() as u32 - 1,
+            [img.width() as f32, img.height() as f32],
+        );
+        self.pic_textures.insert(name.to_owned(), found);
+        Ok(found)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::FacetFlags;
+    use lib::CatalogBuilder;
+
+    #[test]
+    fn it_maps_hints_to_materials() {
+        let filled = FacetFlags::FILL_BACKGROUND | FacetFlags::HAVE_TEXCOORDS;
+        let hints = filled.render_hints();
+        assert!(hints.fill_background);
+        assert!(hints.double_sided);
+        let material = hints.gltf_material(Some(0));
+        assert_eq!(material.alpha_mode, Valid(AlphaMode::Opaque));
+        assert!(material.double_sided);
+
+        let cutout = FacetFlags::HAVE_TEXCOORDS | FacetFlags::HAVE_MATERIAL;
+        let material = cutout.render_hints().gltf_material(Some(0));
+        assert_eq!(material.alpha_mode, Valid(AlphaMode::Mask));
+        assert!(!material.double_sided);
+    }
+
+    #[test]
+    fn it_can_export_a_shape() -> Fallible<()> {
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["FA:F18.SH".to_owned()])?;
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let shape = RawShape::from_bytes(&catalog.read_sync(fid)?)?;
+            let palette = Palette::system(&catalog)?;
+            let root = shape.export_gltf(&catalog, &palette)?;
+
+            assert_eq!(root.nodes.len(), 1);
+            assert!(!root.images.is_empty());
+            assert_eq!(root.images.len(), root.textures.len());
+            let mut textured = 0;
+            for primitive in &root.meshes[0].primitives {
+                let indices = &root.accessors[primitive.indices.unwrap().value()];
+                assert!(indices.count > 0);
+                assert_eq!(indices.count % 3, 0);
+                let material = &root.materials[primitive.material.unwrap().value()];
+                let uvs = primitive.attributes.get(&Valid(Semantic::TexCoords(0)));
+                let texture = &material.pbr_metallic_roughness.base_color_texture;
+                assert_eq!(uvs.is_some(), texture.is_some());
+                if uvs.is_some() {
+                    textured += 1;
+                }
+            }
+            assert!(textured > 0);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_can_export_frame_animations() -> Fallible<()> {
+        let (catalog, inputs) = CatalogBuilder::build_and_select(&["*:*.SH".to_owned()])?;
+        let mut animated = 0;
+        for &fid in &inputs {
+            let shape = RawShape::from_bytes(&catalog.read_sync(fid)?)?;
+            let frame_counts = shape
+                .instrs
+                .iter()
+                .filter_map(|instr| match instr {
+                    Instr::JumpToFrame(frames) => Some(frames.num_frames()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            if frame_counts.is_empty() {
+                continue;
+            }
+            animated += 1;
+
+            let root = build_frame_animation(&shape, 0.25)?;
+            assert_eq!(root.animations.len(), frame_counts.len());
+            for (animation, &count) in root.animations.iter().zip(&frame_counts) {
+                assert_eq!(animation.channels.len(), count);
+                for sampler in &animation.samplers {
+                    assert_eq!(sampler.interpolation, Valid(Interpolation::Step));
+                    assert_eq!(root.accessors[sampler.input.value()].count as usize, count);
+                    assert_eq!(root.accessors[sampler.output.value()].count as usize, count);
+                }
+            }
+        }
+        assert!(animated > 0);
+        Ok(())
+    }
+}
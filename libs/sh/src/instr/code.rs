@@ -14,13 +14,14 @@
 // along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
 use crate::{instr::read_name, Instr, RawShape, UnknownData, SHAPE_LOAD_BASE};
 use ansi::ansi;
+use byteorder::{ByteOrder, LittleEndian};
 use failure::{bail, ensure, Fallible};
 use i386::{ByteCode, Memonic, Operand};
 use lazy_static::lazy_static;
 use log::trace;
 use peff::{Thunk, PE};
 use reverse::bs2s;
-use std::{cmp, collections::HashSet, mem};
+use std::{cmp, collections::HashSet};
 
 lazy_static! {
     pub static ref DATA_RELOCATIONS: HashSet<String> = {
@@ -86,10 +87,7 @@ impl X86Trampoline {
 
     pub fn from_pe(offset: usize, pe: &PE) -> Fallible<Self> {
         ensure!(Self::has_trampoline(offset, pe), "not a trampoline");
-        let target = {
-            let vp: &[u32] = unsafe { mem::transmute(&pe.code[offset + 2..offset + 6]) };
-            vp[0]
-        };
+        let target = LittleEndian::read_u32(&pe.code[offset + 2..]);
 
         let thunk = Self::find_matching_thunk(target, pe)?;
         let is_data = DATA_RELOCATIONS.contains(&thunk.name);
@@ -213,6 +211,22 @@ impl X86Message {
         self.offset
     }
 
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    // Re-read the message from the shape's code. These are the strings that the x86 hands
+    // to _ErrorExit, so we expect plain ascii.
+    pub fn text(&self, shape: &RawShape) -> Fallible<String> {
+        ensure!(
+            self.offset < shape.pe.code.len(),
+            "message offset is outside of the shape"
+        );
+        let text = read_name(&shape.pe.code[self.offset..])?;
+        ensure!(text.is_ascii(), "expected an ascii message");
+        Ok(text)
+    }
+
     pub fn show(&self) -> String {
         format!(
             "@{:04X} {}Messg{}: {}{}{}",
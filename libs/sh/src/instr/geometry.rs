@@ -12,12 +12,14 @@
 //
 // You should have received a copy of the GNU General Public License
 // along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
-use crate::instr::read_name;
+use crate::instr::{read_name, ShError};
 use ansi::ansi;
 use bitflags::bitflags;
+use byteorder::{ByteOrder, LittleEndian};
 use failure::{bail, ensure, Fallible};
+use pal::Palette;
 use reverse::p2s;
-use std::{mem, slice::Iter};
+use std::slice::Iter;
 
 #[derive(Debug)]
 pub struct TextureRef {
@@ -99,11 +101,10 @@ impl TextureIndex {
 
     pub fn from_bytes_after(offset: usize, data: &[u8]) -> Fallible<Self> {
         assert_eq!(data[0], Self::MAGIC);
-        let data2: &[u16] = unsafe { mem::transmute(&data[2..]) };
         Ok(TextureIndex {
             offset,
             unk0: data[1],
-            kind: TextureIndexKind::from_u16(data2[0])?,
+            kind: TextureIndexKind::from_u16(LittleEndian::read_u16(&data[2..]))?,
         })
     }
 
@@ -140,10 +141,8 @@ impl VertexBuf {
     pub fn from_bytes_after(offset: usize, data: &[u8]) -> Fallible<Self> {
         assert_eq!(data[0], Self::MAGIC);
         assert_eq!(data[1], 0);
-        let head: &[u16] = unsafe { mem::transmute(&data[2..6]) };
-        let words: &[i16] = unsafe { mem::transmute(&data[6..]) };
-        let nverts = head[0] as usize;
-        let target_offset = head[1] as usize;
+        let nverts = LittleEndian::read_u16(&data[2..]) as usize;
+        let target_offset = LittleEndian::read_u16(&data[4..]) as usize;
         ensure!(
             target_offset % 8 == 0,
             "expected the vert buffer target offset to be a multiple of 8"
@@ -154,9 +153,10 @@ impl VertexBuf {
             verts: Vec::with_capacity(nverts),
         };
         for i in 0..nverts {
-            let x = words[i * 3];
-            let y = words[i * 3 + 1];
-            let z = words[i * 3 + 2];
+            let at = 6 + i * 6;
+            let x = LittleEndian::read_i16(&data[at..]);
+            let y = LittleEndian::read_i16(&data[at + 2..]);
+            let z = LittleEndian::read_i16(&data[at + 4..]);
             buf.verts.push([x, y, z]);
         }
         Ok(buf)
@@ -166,6 +166,18 @@ impl VertexBuf {
         6 + self.verts.len() * 6
     }
 
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![Self::MAGIC, 0];
+        out.extend_from_slice(&(self.verts.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(self.target_offset as u16 * 8).to_le_bytes());
+        for v in &self.verts {
+            for c in v {
+                out.extend_from_slice(&c.to_le_bytes());
+            }
+        }
+        out
+    }
+
     pub fn magic(&self) -> &'static str {
         "82"
     }
@@ -206,12 +218,21 @@ impl VertexBuf {
     }
 }
 
+fn fan<T: Copy>(polygon: &[T]) -> impl Iterator<Item = [T; 3]> + '_ {
+    polygon
+        .get(1..)
+        .unwrap_or(&[])
+        .windows(2)
+        .map(move |pair| [polygon[0], pair[0], pair[1]])
+}
+
 bitflags! {
     pub struct FacetFlags : u16 {
         const UNK0                 = 0b0000_1000_0000_0000;
         const USE_SHORT_INDICES    = 0b0000_0100_0000_0000;
         const USE_SHORT_MATERIAL   = 0b0000_0010_0000_0000;
         const USE_BYTE_TEXCOORDS   = 0b0000_0001_0000_0000;
+        // The renderer blends textures on these like FILL_BACKGROUND; that is a guess.
         const UNK1                 = 0b0000_0000_1000_0000;
         const HAVE_MATERIAL        = 0b0000_0000_0100_0000;
         const UNK2                 = 0b0000_0000_0010_0000;
@@ -219,17 +240,80 @@ bitflags! {
         const UNK4                 = 0b0000_0000_0000_1000;
         const HAVE_TEXCOORDS       = 0b0000_0000_0000_0100;
         const FILL_BACKGROUND      = 0b0000_0000_0000_0010;
+        // See UNK1.
         const UNK5                 = 0b0000_0000_0000_0001;
     }
 }
 
+// The name of each flag bit, along with what we think it does where we have any idea.
+const FACET_FLAG_NAMES: [(FacetFlags, &str); 12] = [
+    (FacetFlags::UNK0, "UNK0"),
+    (FacetFlags::USE_SHORT_INDICES, "USE_SHORT_INDICES"),
+    (FacetFlags::USE_SHORT_MATERIAL, "USE_SHORT_MATERIAL"),
+    (FacetFlags::USE_BYTE_TEXCOORDS, "USE_BYTE_TEXCOORDS"),
+    (FacetFlags::UNK1, "UNK1(blend?)"),
+    (FacetFlags::HAVE_MATERIAL, "HAVE_MATERIAL"),
+    (FacetFlags::UNK2, "UNK2"),
+    (FacetFlags::UNK3, "UNK3"),
+    (FacetFlags::UNK4, "UNK4"),
+    (FacetFlags::HAVE_TEXCOORDS, "HAVE_TEXCOORDS"),
+    (FacetFlags::FILL_BACKGROUND, "FILL_BACKGROUND"),
+    (FacetFlags::UNK5, "UNK5(blend?)"),
+];
+
 impl FacetFlags {
-    fn from_u16(flags: u16) -> FacetFlags {
-        unsafe { mem::transmute(flags) }
+    // Only the low 12 bits have ever been seen set, so anything else is an error.
+    fn from_u16(flags: u16) -> Fallible<FacetFlags> {
+        FacetFlags::from_bits(flags).ok_or_else(|| ShError::InvalidFacetFlags { flags }.into())
+    }
+
+    // Every set bit by name, separated by |.
+    pub fn describe(self) -> String {
+        FACET_FLAG_NAMES
+            .iter()
+            .filter(|(flag, _)| self.contains(*flag))
+            .map(|(_, name)| *name)
+            .collect::<Vec<_>>()
+            .join("|")
+    }
+
+    pub fn render_hints(self) -> RenderHints {
+        RenderHints {
+            fill_background: self.contains(FacetFlags::FILL_BACKGROUND),
+            // Without a material there is no normal to tell us which side is the front, and
+            // the vertex order is not reliable, so we have to draw both sides.
+            double_sided: !self.contains(FacetFlags::HAVE_MATERIAL),
+        }
     }
+}
+
+// What the facet flags tell us about how a facet should be drawn.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct RenderHints {
+    // Transparent texels are drawn in the background color instead of being cut out.
+    pub fill_background: bool,
+    pub double_sided: bool,
+}
 
-    pub fn to_u16(self) -> u16 {
-        unsafe { mem::transmute(self) }
+// The material block of a facet that has one: a pad byte, the face normal as three i16
+// scaled such that 0x7FFF is 1, and then three more values. The short form, used when
+// USE_SHORT_MATERIAL is set, stores those last three as i8 instead of i16; we widen them here.
+// They look like a point on or an offset from the face, but nothing reads them yet.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Material {
+    pub normal: [i16; 3],
+    pub offset: [i16; 3],
+    pub short: bool,
+}
+
+impl Material {
+    pub fn unit_normal(&self) -> [f32; 3] {
+        let component = |v: i16| f32::from(v) / 32767f32;
+        [
+            component(self.normal[0]),
+            component(self.normal[1]),
+            component(self.normal[2]),
+        ]
     }
 }
 
@@ -361,9 +445,7 @@ impl Facet {
         let mut off = 1;
 
         let flags_offset = off;
-        let flags_arr: &[u16] = unsafe { mem::transmute(&data[flags_offset..]) };
-        assert_eq!(flags_arr[0] & 0xF000, 0u16);
-        let flags = FacetFlags::from_u16(flags_arr[0]);
+        let flags = FacetFlags::from_u16(LittleEndian::read_u16(&data[flags_offset..]))?;
         off += 2;
 
         let color_offset = off;
@@ -392,9 +474,11 @@ impl Facet {
         // Indexes.
         let indices_offset = off;
         let indices: Vec<u16> = if flags.contains(FacetFlags::USE_SHORT_INDICES) {
-            let index_u16: &[u16] = unsafe { mem::transmute(&data[off..]) };
+            let indices = (0..index_count)
+                .map(|i| LittleEndian::read_u16(&data[off + i * 2..]))
+                .collect();
             off += index_count * 2;
-            index_u16[0..index_count].to_vec()
+            indices
         } else {
             let index_u8 = &data[off..off + index_count];
             off += index_count;
@@ -407,14 +491,16 @@ impl Facet {
         let mut tex_coords = Vec::with_capacity(index_count);
         if flags.contains(FacetFlags::HAVE_TEXCOORDS) {
             let tc_u8 = &data[off..];
-            let tc_u16: &[u16] = unsafe { mem::transmute(tc_u8) };
             for i in 0..index_count {
                 let (u, v) = if flags.contains(FacetFlags::USE_BYTE_TEXCOORDS) {
                     off += 2;
                     (u16::from(tc_u8[i * 2]), u16::from(tc_u8[i * 2 + 1]))
                 } else {
                     off += 4;
-                    (tc_u16[i * 2], tc_u16[i * 2 + 1])
+                    (
+                        LittleEndian::read_u16(&tc_u8[i * 4..]),
+                        LittleEndian::read_u16(&tc_u8[i * 4 + 2..]),
+                    )
                 };
                 tex_coords.push([u, v]);
             }
@@ -449,10 +535,116 @@ impl Facet {
         })
     }
 
+    // Decode raw_material in the layout given by the flags; see Material.
+    pub fn material(&self) -> Option<Material> {
+        if !self.flags.contains(FacetFlags::HAVE_MATERIAL) {
+            return None;
+        }
+        let m = &self.raw_material;
+        let short = self.flags.contains(FacetFlags::USE_SHORT_MATERIAL);
+        let normal = [
+            LittleEndian::read_i16(&m[1..]),
+            LittleEndian::read_i16(&m[3..]),
+            LittleEndian::read_i16(&m[5..]),
+        ];
+        let offset = if short {
+            [
+                i16::from(m[7] as i8),
+                i16::from(m[8] as i8),
+                i16::from(m[9] as i8),
+            ]
+        } else {
+            [
+                LittleEndian::read_i16(&m[7..]),
+                LittleEndian::read_i16(&m[9..]),
+                LittleEndian::read_i16(&m[11..]),
+            ]
+        };
+        Some(Material {
+            normal,
+            offset,
+            short,
+        })
+    }
+
+    pub fn material_normal(&self) -> Option<[f32; 3]> {
+        self.material().map(|material| material.unit_normal())
+    }
+
+    // The facet's color as RGBA. Indices outside the palette come back as magenta, so that
+    // they stand out.
+    pub fn resolve_color(&self, palette: &Palette) -> [u8; 4] {
+        palette
+            .rgba(self.color as usize)
+            .map(|rgba| rgba.data)
+            .unwrap_or([255, 0, 255, 255])
+    }
+
+    pub fn render_hints(&self) -> RenderHints {
+        self.flags.render_hints()
+    }
+
+    // The polygon as a fan of triangles around its first vertex: (0, i, i + 1).
+    pub fn triangles(&self) -> impl Iterator<Item = [u16; 3]> + '_ {
+        fan(&self.indices)
+    }
+
+    // The texture coordinates for each of the triangles, if the facet is textured. Byte
+    // coordinates have already been widened, so these are the same whatever the flags say.
+    pub fn triangle_texcoords(&self) -> Option<impl Iterator<Item = [[u16; 2]; 3]> + '_> {
+        if self.flags.contains(FacetFlags::HAVE_TEXCOORDS)
+            && self.tex_coords.len() == self.indices.len()
+        {
+            Some(fan(&self.tex_coords))
+        } else {
+            None
+        }
+    }
+
+    // Ground shadows are drawn as flat, untextured polys. This only looks at what the facet
+    // itself knows; a shadow must also sit on the shape's ground plane, which build_mesh checks
+    // when asked to skip shadows.
+    pub fn is_shadow(&self) -> bool {
+        if self.flags.contains(FacetFlags::HAVE_TEXCOORDS) {
+            return false;
+        }
+        match self.material_normal() {
+            // Up or down, in a shape's z-down axes.
+            Some(n) => n[2].abs() > 0.99,
+            None => true,
+        }
+    }
+
     pub fn size(&self) -> usize {
         self.length
     }
 
+    // Encode the facet from its fields, in the layout given by its flags.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![Self::MAGIC];
+        out.extend_from_slice(&self.flags.bits().to_le_bytes());
+        out.push(self.color);
+        out.extend_from_slice(&self.raw_material);
+        out.push(self.indices.len() as u8);
+        for &index in &self.indices {
+            if self.flags.contains(FacetFlags::USE_SHORT_INDICES) {
+                out.extend_from_slice(&index.to_le_bytes());
+            } else {
+                out.push(index as u8);
+            }
+        }
+        if self.flags.contains(FacetFlags::HAVE_TEXCOORDS) {
+            for tc in self.tex_coords.iter().flatten() {
+                if self.flags.contains(FacetFlags::USE_BYTE_TEXCOORDS) {
+                    out.push(*tc as u8);
+                } else {
+                    out.extend_from_slice(&tc.to_le_bytes());
+                }
+            }
+        }
+        out
+    }
+
     pub fn magic(&self) -> &'static str {
         "Facet(FC)"
     }
@@ -573,15 +765,13 @@ impl VertexNormal {
 
     pub fn from_bytes_after(offset: usize, data: &[u8]) -> Fallible<Self> {
         assert_eq!(data[0], Self::MAGIC);
-        let uword_ref: &[i16] = unsafe { mem::transmute(&data[1..]) };
-        let index = uword_ref[0] as usize;
-        let idata: &[i8] = unsafe { mem::transmute(&data[4..]) };
+        let index = LittleEndian::read_i16(&data[1..]) as usize;
         Ok(Self {
             offset,
             data: data.as_ptr(),
             index,
             color: data[3],
-            norm: [idata[0], idata[1], idata[2]],
+            norm: [data[4] as i8, data[5] as i8, data[6] as i8],
         })
     }
 
@@ -589,6 +779,14 @@ impl VertexNormal {
         Self::SIZE
     }
 
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![Self::MAGIC];
+        out.extend_from_slice(&(self.index as i16).to_le_bytes());
+        out.push(self.color);
+        out.extend(self.norm.iter().map(|&n| n as u8));
+        out
+    }
+
     pub fn magic(&self) -> &'static str {
         "F6"
     }
@@ -12,10 +12,11 @@
 //
 // You should have received a copy of the GNU General Public License
 // along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
+use crate::instr::resolve_relative;
 use ansi::ansi;
+use byteorder::{ByteOrder, LittleEndian};
 use failure::{ensure, Fallible};
 use reverse::p2s;
-use std::mem;
 
 #[derive(Debug)]
 pub struct Jump {
@@ -31,8 +32,7 @@ impl Jump {
     pub fn from_bytes_after(offset: usize, data: &[u8]) -> Fallible<Self> {
         assert_eq!(data[0], Self::MAGIC);
         assert_eq!(data[1], 0x00);
-        let word_ref: &[i16] = unsafe { mem::transmute(&data[2..]) };
-        let offset_to_target = word_ref[0] as isize;
+        let offset_to_target = LittleEndian::read_i16(&data[2..]) as isize;
         Ok(Self {
             offset,
             data: data[0..Self::SIZE].as_ptr(),
@@ -52,8 +52,12 @@ impl Jump {
         self.offset
     }
 
+    pub fn offset_to_target(&self) -> isize {
+        self.offset_to_target
+    }
+
     pub fn target_byte_offset(&self) -> usize {
-        (self.offset + Self::SIZE).wrapping_add(self.offset_to_target as usize)
+        resolve_relative(self.offset, Self::SIZE, self.offset_to_target)
     }
 
     pub fn show(&self) -> String {
@@ -87,8 +91,7 @@ impl JumpToDamage {
     pub fn from_bytes_after(offset: usize, data: &[u8]) -> Fallible<Self> {
         assert_eq!(data[0], Self::MAGIC);
         assert_eq!(data[1], 0x00);
-        let word_ref: &[i16] = unsafe { mem::transmute(&data[2..]) };
-        let delta_to_damage = word_ref[0] as isize;
+        let delta_to_damage = LittleEndian::read_i16(&data[2..]) as isize;
         Ok(Self {
             offset,
             data: data.as_ptr(),
@@ -109,7 +112,7 @@ impl JumpToDamage {
     }
 
     pub fn damage_byte_offset(&self) -> usize {
-        (self.offset + Self::SIZE).wrapping_add(self.delta_to_damage as usize)
+        resolve_relative(self.offset, Self::SIZE, self.delta_to_damage)
     }
 
     pub fn show(&self) -> String {
@@ -149,10 +152,9 @@ impl JumpToDetail {
     pub fn from_bytes_after(offset: usize, data: &[u8]) -> Fallible<Self> {
         assert_eq!(data[0], Self::MAGIC);
         assert_eq!(data[1], 0x00);
-        let word_ref: &[i16] = unsafe { mem::transmute(&data[2..]) };
-        let level = word_ref[1] as u16;
+        let level = LittleEndian::read_u16(&data[4..]);
         assert!(level >= 1 && level <= 3);
-        let offset_to_target = word_ref[0] as isize;
+        let offset_to_target = LittleEndian::read_i16(&data[2..]) as isize;
         Ok(Self {
             offset,
             level,
@@ -174,7 +176,7 @@ impl JumpToDetail {
     }
 
     pub fn target_byte_offset(&self) -> usize {
-        (self.offset + Self::SIZE).wrapping_add(self.offset_to_target as usize)
+        resolve_relative(self.offset, Self::SIZE, self.offset_to_target)
     }
 
     pub fn show(&self) -> String {
@@ -212,8 +214,7 @@ impl JumpToFrame {
     pub fn from_bytes_after(offset: usize, data: &[u8]) -> Fallible<Self> {
         assert_eq!(data[0], Self::MAGIC);
         assert_eq!(data[1], 0x00);
-        let words: &[u16] = unsafe { mem::transmute(&data[2..]) };
-        let count = words[0] as usize;
+        let count = LittleEndian::read_u16(&data[2..]) as usize;
         ensure!(
             count <= 6,
             "found jump-to-frame instruction with more than 6 frames of animation"
@@ -225,7 +226,9 @@ impl JumpToFrame {
             data: data.as_ptr(),
 
             count,
-            frame_offsets: words[1..=count].to_owned(),
+            frame_offsets: (1..=count)
+                .map(|i| LittleEndian::read_u16(&data[2 + i * 2..]))
+                .collect(),
         })
     }
 
@@ -273,6 +276,17 @@ impl JumpToFrame {
     }
 }
 
+// The parameters of a JumpToLOD, as best we understand them. Neither is confirmed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LodParams {
+    // unk0: seems to rank the levels; the game may use it to pick a level when the
+    // ranges alone do not decide.
+    pub priority: u16,
+
+    // unk1: see JumpToLOD::switch_range_ft.
+    pub switch_range_ft: f32,
+}
+
 #[derive(Debug)]
 pub struct JumpToLOD {
     pub offset: usize,
@@ -290,10 +304,9 @@ impl JumpToLOD {
     pub fn from_bytes_after(offset: usize, data: &[u8]) -> Fallible<Self> {
         assert_eq!(data[0], Self::MAGIC);
         assert_eq!(data[1], 0x00);
-        let word_ref: &[u16] = unsafe { mem::transmute(&data[2..]) };
-        let unk0 = word_ref[0];
-        let unk1 = word_ref[1];
-        let target_offset = word_ref[2] as i16 as isize;
+        let unk0 = LittleEndian::read_u16(&data[2..]);
+        let unk1 = LittleEndian::read_u16(&data[4..]);
+        let target_offset = LittleEndian::read_i16(&data[6..]) as isize;
         Ok(Self {
             offset,
             unk0,
@@ -316,7 +329,20 @@ impl JumpToLOD {
     }
 
     pub fn target_byte_offset(&self) -> usize {
-        (self.offset + Self::SIZE).wrapping_add(self.target_offset as usize)
+        resolve_relative(self.offset, Self::SIZE, self.target_offset)
+    }
+
+    // Our best guess is that unk1 is the camera distance, in feet, past which the section
+    // that follows is skipped in favor of the target. This is unconfirmed.
+    pub fn switch_range_ft(&self) -> f32 {
+        f32::from(self.unk1)
+    }
+
+    pub fn params(&self) -> LodParams {
+        LodParams {
+            priority: self.unk0,
+            switch_range_ft: self.switch_range_ft(),
+        }
     }
 
     pub fn show(&self) -> String {
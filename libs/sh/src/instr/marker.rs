@@ -13,9 +13,9 @@
 // You should have received a copy of the GNU General Public License
 // along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
 use ansi::ansi;
+use byteorder::{ByteOrder, LittleEndian};
 use failure::Fallible;
 use reverse::p2s;
-use std::mem;
 
 #[derive(Debug)]
 pub struct PtrToObjEnd {
@@ -31,8 +31,7 @@ impl PtrToObjEnd {
     pub fn from_bytes_after(offset: usize, data: &[u8]) -> Fallible<Self> {
         assert_eq!(data[0], Self::MAGIC);
         assert_eq!(data[1], 0x00);
-        let word_ref: &[u16] = unsafe { mem::transmute(&data[2..]) };
-        let delta_to_end = word_ref[0] as usize;
+        let delta_to_end = LittleEndian::read_u16(&data[2..]) as usize;
         Ok(Self {
             offset,
             data: data.as_ptr(),
@@ -12,10 +12,11 @@
 //
 // You should have received a copy of the GNU General Public License
 // along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
+use crate::instr::resolve_relative;
 use ansi::ansi;
+use byteorder::{ByteOrder, LittleEndian};
 use failure::Fallible;
 use reverse::p2s;
-use std::mem;
 
 // When points to a VertexBuf, it "unmasks" the facets that occur after that vertex buffer up
 // to the next vertex buffer or Header. When it points elsewhere, :shrug:. It usually points
@@ -34,8 +35,7 @@ impl Unmask {
     pub fn from_bytes_after(offset: usize, data: &[u8]) -> Fallible<Self> {
         assert_eq!(data[0], Self::MAGIC);
         assert_eq!(data[1], 0x00);
-        let word_ref: &[u16] = unsafe { mem::transmute(&data[2..]) };
-        let offset_to_next = word_ref[0] as usize;
+        let offset_to_next = LittleEndian::read_u16(&data[2..]) as usize;
         Ok(Self {
             offset,
             data: data[0..Self::SIZE].as_ptr(),
@@ -56,7 +56,7 @@ impl Unmask {
     }
 
     pub fn target_byte_offset(&self) -> usize {
-        self.offset + Self::SIZE + self.offset_to_next
+        resolve_relative(self.offset, Self::SIZE, self.offset_to_next as isize)
     }
 
     pub fn show(&self) -> String {
@@ -91,8 +91,7 @@ impl Unmask4 {
     pub fn from_bytes_after(offset: usize, data: &[u8]) -> Fallible<Self> {
         assert_eq!(data[0], Self::MAGIC);
         assert_eq!(data[1], 0x00);
-        let dword_ref: &[u32] = unsafe { mem::transmute(&data[2..]) };
-        let offset_to_next = dword_ref[0] as usize;
+        let offset_to_next = LittleEndian::read_u32(&data[2..]) as usize;
         Ok(Self {
             offset,
             data: data[0..Self::SIZE].as_ptr(),
@@ -113,7 +112,7 @@ impl Unmask4 {
     }
 
     pub fn target_byte_offset(&self) -> usize {
-        self.offset + Self::SIZE + self.offset_to_next
+        resolve_relative(self.offset, Self::SIZE, self.offset_to_next as isize)
     }
 
     pub fn show(&self) -> String {
@@ -159,17 +158,15 @@ impl XformUnmask {
     pub fn from_bytes_after(offset: usize, data: &[u8]) -> Fallible<Self> {
         assert_eq!(data[0], Self::MAGIC);
         assert_eq!(data[1], 0x00);
-        let word_ref: &[i16] = unsafe { mem::transmute(&data[2..]) };
-        let t0 = word_ref[0];
-        let t1 = word_ref[1];
-        let t2 = word_ref[2];
-        let a0 = word_ref[3];
-        let a1 = word_ref[4];
-        let a2 = word_ref[5];
+        let t0 = LittleEndian::read_i16(&data[2..]);
+        let t1 = LittleEndian::read_i16(&data[4..]);
+        let t2 = LittleEndian::read_i16(&data[6..]);
+        let a0 = LittleEndian::read_i16(&data[8..]);
+        let a1 = LittleEndian::read_i16(&data[10..]);
+        let a2 = LittleEndian::read_i16(&data[12..]);
         let mut xform_base: [u8; 12] = Default::default();
         xform_base.copy_from_slice(&data[2..14]);
-        let uword_ref: &[u16] = unsafe { mem::transmute(&data[14..]) };
-        let offset_to_next = uword_ref[0] as usize;
+        let offset_to_next = LittleEndian::read_u16(&data[14..]) as usize;
         Ok(Self {
             offset,
             data: data[0..Self::SIZE].as_ptr(),
@@ -197,7 +194,7 @@ impl XformUnmask {
     }
 
     pub fn target_byte_offset(&self) -> usize {
-        self.offset + Self::SIZE + self.offset_to_next
+        resolve_relative(self.offset, Self::SIZE, self.offset_to_next as isize)
     }
 
     pub fn show(&self) -> String {
@@ -253,17 +250,15 @@ impl XformUnmask4 {
     pub fn from_bytes_after(offset: usize, data: &[u8]) -> Fallible<Self> {
         assert_eq!(data[0], Self::MAGIC);
         assert_eq!(data[1], 0x00);
-        let word_ref: &[i16] = unsafe { mem::transmute(&data[2..]) };
-        let t0 = word_ref[0];
-        let t1 = word_ref[1];
-        let t2 = word_ref[2];
-        let a0 = word_ref[3];
-        let a1 = word_ref[4];
-        let a2 = word_ref[5];
+        let t0 = LittleEndian::read_i16(&data[2..]);
+        let t1 = LittleEndian::read_i16(&data[4..]);
+        let t2 = LittleEndian::read_i16(&data[6..]);
+        let a0 = LittleEndian::read_i16(&data[8..]);
+        let a1 = LittleEndian::read_i16(&data[10..]);
+        let a2 = LittleEndian::read_i16(&data[12..]);
         let mut xform_base: [u8; 12] = Default::default();
         xform_base.copy_from_slice(&data[2..14]);
-        let dword_ref: &[u32] = unsafe { mem::transmute(&data[14..]) };
-        let offset_to_next = dword_ref[0] as usize;
+        let offset_to_next = LittleEndian::read_u32(&data[14..]) as usize;
         Ok(Self {
             offset,
             data: data[0..Self::SIZE].as_ptr(),
@@ -291,7 +286,7 @@ impl XformUnmask4 {
     }
 
     pub fn target_byte_offset(&self) -> usize {
-        self.offset + Self::SIZE + self.offset_to_next
+        resolve_relative(self.offset, Self::SIZE, self.offset_to_next as isize)
     }
 
     pub fn show(&self) -> String {
@@ -66,6 +66,8 @@ pub struct EndOfShape {
 
 // 1 2 3 2 1 0*
 impl EndOfShape {
+    pub const MARKER: [u8; 5] = [1, 2, 3, 2, 1];
+
     pub fn from_bytes_after(offset: usize, data: &[u8]) -> Fallible<Self> {
         Ok(Self {
             offset,
@@ -73,6 +75,21 @@ impl EndOfShape {
         })
     }
 
+    // Everything after the first marker. Usually this is only zero padding, but some shapes
+    // repeat the marker or carry other bytes that we do not understand yet.
+    pub fn tail(&self) -> &[u8] {
+        if self.data.starts_with(&Self::MARKER) {
+            &self.data[Self::MARKER.len()..]
+        } else {
+            &self.data
+        }
+    }
+
+    // A single marker followed by nothing but padding.
+    pub fn is_standard(&self) -> bool {
+        self.data.starts_with(&Self::MARKER) && self.tail().iter().all(|&b| b == 0)
+    }
+
     pub fn size(&self) -> usize {
         self.data.len()
     }
@@ -24,8 +24,10 @@ use std::str;
 
 pub use crate::instr::{
     code::{X86Code, X86Message, X86Trampoline},
-    geometry::{Facet, FacetFlags, TextureIndex, TextureRef, VertexBuf, VertexNormal},
-    jump::{Jump, JumpToDamage, JumpToDetail, JumpToFrame, JumpToLOD},
+    geometry::{
+        Facet, FacetFlags, Material, RenderHints, TextureIndex, TextureRef, VertexBuf, VertexNormal,
+    },
+    jump::{Jump, JumpToDamage, JumpToDetail, JumpToFrame, JumpToLOD, LodParams},
     marker::PtrToObjEnd,
     mask::{Unmask, Unmask4, XformUnmask, XformUnmask4},
     meta::{EndOfObject, EndOfShape, Pad1E, SourceRef},
@@ -35,6 +37,84 @@ pub use crate::instr::{
 pub enum ShError {
     #[fail(display = "name ran off end of file")]
     NameUnending {},
+    #[fail(
+        display = "expected 12321 end of shape sequence before {:04X}",
+        searched_from
+    )]
+    NoEndMarker { searched_from: usize },
+    #[fail(
+        display = "found {} trampolines at the end of the shape, but {} imports",
+        found, expected
+    )]
+    TrampolineCountMismatch { found: usize, expected: usize },
+    #[fail(
+        display = "relative target {} from {:04X} lands outside of {} bytes of code",
+        delta, from, limit
+    )]
+    RelativeTargetOutOfBounds {
+        from: usize,
+        delta: isize,
+        limit: usize,
+    },
+    #[fail(
+        display = "facet at {:04X} uses vertex {}, but only {} vertices are loaded",
+        offset, index, vertex_count
+    )]
+    FacetIndexOutOfRange {
+        offset: usize,
+        index: u16,
+        vertex_count: usize,
+    },
+    #[fail(display = "facet flags {:04X} set bits in the high nibble", flags)]
+    InvalidFacetFlags { flags: u16 },
+    #[fail(
+        display = "unknown instruction {:02X} at {:04X}: {}",
+        opcode, offset, dump
+    )]
+    UnknownInstruction {
+        opcode: u8,
+        offset: usize,
+        dump: String,
+    },
+}
+
+// Instructions that can send drawing somewhere other than the next instruction.
+pub trait BranchTarget {
+    // Every byte offset that this instruction can transfer control to.
+    fn targets(&self) -> Vec<usize>;
+}
+
+macro_rules! branch_target {
+    ($name:ident, $target:ident) => {
+        impl BranchTarget for $name {
+            fn targets(&self) -> Vec<usize> {
+                vec![self.$target()]
+            }
+        }
+    };
+}
+
+branch_target!(Jump, target_byte_offset);
+branch_target!(JumpToDamage, damage_byte_offset);
+branch_target!(JumpToDetail, target_byte_offset);
+branch_target!(JumpToLOD, target_byte_offset);
+branch_target!(PtrToObjEnd, end_byte_offset);
+branch_target!(Unmask, target_byte_offset);
+branch_target!(Unmask4, target_byte_offset);
+branch_target!(XformUnmask, target_byte_offset);
+branch_target!(XformUnmask4, target_byte_offset);
+
+impl BranchTarget for JumpToFrame {
+    fn targets(&self) -> Vec<usize> {
+        (0..self.num_frames())
+            .map(|n| self.target_for_frame(n))
+            .collect()
+    }
+}
+
+// Jump-like instructions store their target as a delta from the end of the instruction.
+pub fn resolve_relative(from: usize, size: usize, delta: isize) -> usize {
+    (from + size).wrapping_add(delta as usize)
 }
 
 pub fn read_name(n: &[u8]) -> Fallible<String> {
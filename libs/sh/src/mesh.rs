@@ -0,0 +1,1026 @@
+// This file is part of OpenFA.
+//
+// OpenFA is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// OpenFA is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
+use crate::{Facet, FacetFlags, Instr, RawShape};
+use failure::Fallible;
+use log::trace;
+use std::collections::{BTreeSet, HashMap};
+
+// A CPU side view of the geometry in a shape, suitable for exporting. Vertices are
+// kept welded, as they are in the shape's vertex pool, and positions are in the
+// shape's native coordinate system and units.
+#[derive(Clone, Debug, Default)]
+pub struct MeshData {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    // Only filled in when uv seams are split; otherwise texture coordinates live on the faces.
+    pub tex_coords: Vec<Option<[u16; 2]>>,
+    // Only filled in by compute_tangents. The w component is the handedness of the
+    // bitangent, as glTF expects.
+    pub tangents: Vec<[f32; 4]>,
+    pub faces: Vec<MeshFace>,
+}
+
+// One facet of the shape, with indices into the welded vertex pool.
+#[derive(Clone, Debug)]
+pub struct MeshFace {
+    pub indices: Vec<u32>,
+    pub tex_coords: Vec<[u16; 2]>,
+    pub texture: Option<String>,
+    pub color: u8,
+    pub flags: FacetFlags,
+    pub normal: [f32; 3],
+    // The normal stored in the facet's material block, when it has one.
+    pub material_normal: Option<[f32; 3]>,
+}
+
+// The axes that exported positions and normals are expressed in. Shapes are stored with
+// x to the right, y towards the tail, and z down.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CoordinateConvention {
+    // Leave everything as it is in the shape.
+    Native,
+
+    // glTF's y up, with the nose pointing down +z and the right wing down -x. This is a
+    // pure rotation of the native axes, so face winding does not need to change.
+    YUp,
+}
+
+impl Default for CoordinateConvention {
+    fn default() -> Self {
+        CoordinateConvention::Native
+    }
+}
+
+impl CoordinateConvention {
+    pub fn transform(self, v: [f32; 3]) -> [f32; 3] {
+        match self {
+            CoordinateConvention::Native => v,
+            CoordinateConvention::YUp => [-v[0], -v[2], -v[1]],
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MeshOptions {
+    // Average facet normals into the vertex normals of any vertex that was not
+    // given an explicit normal by a VertexNormal instruction.
+    pub compute_normals: bool,
+
+    // Flip any face that is wound against its material normal.
+    pub fix_winding: bool,
+
+    // Duplicate any vertex that is used with more than one texture coordinate, so that
+    // every vertex has a single uv, as most export formats expect.
+    pub split_uv_seams: bool,
+
+    // Leave out untextured facets that lie flat on the lowest point of the shape, which is
+    // how shapes draw their shadow on the ground.
+    pub skip_shadows: bool,
+
+    // Fill in per-vertex tangents for normal mapping. Most useful with split_uv_seams.
+    pub compute_tangents: bool,
+
+    // Stop at the first branch to a lower detail or damaged model, rather than skipping over
+    // those sections, so that only the geometry drawn before any branch is kept.
+    pub base_only: bool,
+
+    // The axes to express the final positions and normals in.
+    pub convention: CoordinateConvention,
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn normalize(v: [f32; 3]) -> [f32; 3] {
+    let len = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    if len == 0f32 {
+        return v;
+    }
+    [v[0] / len, v[1] / len, v[2] / len]
+}
+
+impl MeshFace {
+    fn from_facet(facet: &Facet, texture: &Option<String>, positions: &[[f32; 3]]) -> Self {
+        let indices = facet
+            .indices
+            .iter()
+            .map(|&i| u32::from(i))
+            .collect::<Vec<_>>();
+        let mut face = Self {
+            indices,
+            tex_coords: facet.tex_coords.clone(),
+            texture: if facet.flags.contains(FacetFlags::HAVE_TEXCOORDS) {
+                texture.clone()
+            } else {
+                None
+            },
+            color: facet.color,
+            flags: facet.flags,
+            normal: [0f32; 3],
+            material_normal: facet.material_normal(),
+        };
+        face.normal = face.compute_normal(positions);
+        face
+    }
+
+    // Newell's method, so that we get a reasonable answer for slightly non-planar polys.
+    pub fn compute_normal(&self, positions: &[[f32; 3]]) -> [f32; 3] {
+        let mut n = [0f32; 3];
+        for (i, &a) in self.indices.iter().enumerate() {
+            let b = self.indices[(i + 1) % self.indices.len()];
+            if a as usize >= positions.len() || b as usize >= positions.len() {
+                continue;
+            }
+            let p = positions[a as usize];
+            let q = positions[b as usize];
+            n[0] += (p[1] - q[1]) * (p[2] + q[2]);
+            n[1] += (p[2] - q[2]) * (p[0] + q[0]);
+            n[2] += (p[0] - q[0]) * (p[1] + q[1]);
+        }
+        normalize(n)
+    }
+}
+
+impl MeshData {
+    pub fn new(positions: Vec<[f32; 3]>, faces: Vec<MeshFace>) -> Self {
+        let normals = vec![[0f32; 3]; positions.len()];
+        Self {
+            positions,
+            normals,
+            tex_coords: Vec::new(),
+            tangents: Vec::new(),
+            faces,
+        }
+    }
+
+    // Give every vertex the average of the normals of all faces that share it.
+    pub fn compute_smooth_normals(&mut self) {
+        let mut accum = vec![[0f32; 3]; self.positions.len()];
+        for face in &self.faces {
+            for &index in &face.indices {
+                if let Some(n) = accum.get_mut(index as usize) {
+                    n[0] += face.normal[0];
+                    n[1] += face.normal[1];
+                    n[2] += face.normal[2];
+                }
+            }
+        }
+        self.normals = accum.drain(..).map(normalize).collect();
+    }
+
+    // Accumulate a tangent and bitangent for each vertex from the uv gradients of the faces
+    // that share it, then orthogonalize against the vertex normal. This is the same basis
+    // that MikkTSpace builds, without its careful splitting of vertices where the basis
+    // is discontinuous, so split_uv_seams should be run first. Vertices that are not on any
+    // textured face get an arbitrary tangent perpendicular to their normal.
+    pub fn compute_tangents(&mut self) {
+        let mut tangents = vec![[0f32; 3]; self.positions.len()];
+        let mut bitangents = vec![[0f32; 3]; self.positions.len()];
+        for face in &self.faces {
+            if face.tex_coords.len() != face.indices.len()
+                || face
+                    .indices
+                    .iter()
+                    .any(|&i| i as usize >= self.positions.len())
+            {
+                continue;
+            }
+            for i in 1..face.indices.len().saturating_sub(1) {
+                let corners = [0, i, i + 1];
+                let p = corners
+                    .iter()
+                    .map(|&c| self.positions[face.indices[c] as usize]);
+                let p = p.collect::<Vec<_>>();
+                let uv = corners
+                    .iter()
+                    .map(|&c| {
+                        let [u, v] = face.tex_coords[c];
+                        [f32::from(u), f32::from(v)]
+                    })
+                    .collect::<Vec<_>>();
+                let e1 = sub(p[1], p[0]);
+                let e2 = sub(p[2], p[0]);
+                let (du1, dv1) = (uv[1][0] - uv[0][0], uv[1][1] - uv[0][1]);
+                let (du2, dv2) = (uv[2][0] - uv[0][0], uv[2][1] - uv[0][1]);
+                let det = du1 * dv2 - du2 * dv1;
+                if det.abs() < std::f32::EPSILON {
+                    continue;
+                }
+                let r = 1f32 / det;
+                let mut t = [0f32; 3];
+                let mut b = [0f32; 3];
+                for k in 0..3 {
+                    t[k] = (e1[k] * dv2 - e2[k] * dv1) * r;
+                    b[k] = (e2[k] * du1 - e1[k] * du2) * r;
+                }
+                for &c in &corners {
+                    let index = face.indices[c] as usize;
+                    for k in 0..3 {
+                        tangents[index][k] += t[k];
+                        bitangents[index][k] += b[k];
+                    }
+                }
+            }
+        }
+
+        self.tangents = tangents
+            .iter()
+            .zip(&bitangents)
+            .enumerate()
+            .map(|(i, (&t, &b))| {
+                let n = self.normals.get(i).cloned().unwrap_or([0f32, 0f32, 1f32]);
+                let d = dot(n, t);
+                let mut t = normalize([t[0] - n[0] * d, t[1] - n[1] * d, t[2] - n[2] * d]);
+                if dot(t, t) < 0.5 {
+                    // Either untextured or degenerate; pick any axis that is not the normal.
+                    let axis = if n[0].abs() < 0.9 {
+                        [1f32, 0f32, 0f32]
+                    } else {
+                        [0f32, 1f32, 0f32]
+                    };
+                    t = normalize(cross(n, axis));
+                }
+                let w = if dot(cross(n, t), b) < 0f32 {
+                    -1f32
+                } else {
+                    1f32
+                };
+                [t[0], t[1], t[2], w]
+            })
+            .collect();
+    }
+
+    // Faces in FA are not consistently wound. Where a face knows which way it should be
+    // facing, reverse any that are wound the other way.
+    pub fn fix_winding(&mut self) {
+        for face in &mut self.faces {
+            let reference = match face.material_normal {
+                Some(n) => n,
+                None => continue,
+            };
+            let n = face.normal;
+            if n[0] * reference[0] + n[1] * reference[1] + n[2] * reference[2] < 0f32 {
+                face.indices.reverse();
+                face.tex_coords.reverse();
+                face.normal = face.compute_normal(&self.positions);
+            }
+        }
+    }
+
+    // Merge pairs of faces that share an edge, have the same material and texture
+    // coordinates along it, and whose normals are within angle_tol radians of each other, as
+    // long as the merged polygon stays convex. This is repeated until nothing else can be
+    // merged. Only faces that land in the same bucket of material and rounded normal are
+    // compared, so a pair that straddles two buckets is left alone.
+    pub fn merge_coplanar(&mut self, angle_tol: f32) {
+        let min_dot = angle_tol.cos();
+        let scale = 1f32 / angle_tol.max(0.001);
+        let mut buckets = HashMap::new();
+        for (i, face) in self.faces.iter().enumerate() {
+            let n = face.normal;
+            let key = (
+                face.color,
+                face.texture.clone(),
+                face.flags.bits(),
+                face.tex_coords.is_empty(),
+                [
+                    (n[0] * scale).round() as i32,
+                    (n[1] * scale).round() as i32,
+                    (n[2] * scale).round() as i32,
+                ],
+            );
+            buckets.entry(key).or_insert_with(Vec::new).push(i);
+        }
+
+        // Keep each face at the position of the first face merged into it.
+        let mut merged = Vec::with_capacity(self.faces.len());
+        for indices in buckets.values() {
+            let mut firsts = indices.clone();
+            let mut faces = indices
+                .iter()
+                .map(|&i| self.faces[i].clone())
+                .collect::<Vec<_>>();
+            while let Some((a, b, face)) = self.find_coplanar_merge(&faces, min_dot) {
+                faces[a] = face;
+                faces.remove(b);
+                firsts.remove(b);
+            }
+            merged.extend(firsts.into_iter().zip(faces));
+        }
+        merged.sort_by_key(|&(first, _)| first);
+        self.faces = merged.into_iter().map(|(_, face)| face).collect();
+    }
+
+    // Map each directed edge to the face, and the corner in that face, that starts it.
+    fn edge_map(faces: &[MeshFace]) -> HashMap<(u32, u32), (usize, usize)> {
+        let mut edges = HashMap::new();
+        for (i, face) in faces.iter().enumerate() {
+            for (j, &v0) in face.indices.iter().enumerate() {
+                let v1 = face.indices[(j + 1) % face.indices.len()];
+                edges.insert((v0, v1), (i, j));
+            }
+        }
+        edges
+    }
+
+    fn find_coplanar_merge(
+        &self,
+        faces: &[MeshFace],
+        min_dot: f32,
+    ) -> Option<(usize, usize, MeshFace)> {
+        let edges = Self::edge_map(faces);
+        for (a, face) in faces.iter().enumerate() {
+            for (j, &v0) in face.indices.iter().enumerate() {
+                let v1 = face.indices[(j + 1) % face.indices.len()];
+                // Consistently wound neighbors walk the shared edge the other way.
+                let &(b, k) = match edges.get(&(v1, v0)) {
+                    Some(other) => other,
+                    None => continue,
+                };
+                if b <= a || !Self::can_merge(face, j, &faces[b], k, min_dot) {
+                    continue;
+                }
+                let merged = self.merge_faces(face, j, &faces[b], k);
+                if self.is_convex(&merged) {
+                    return Some((a, b, merged));
+                }
+            }
+        }
+        None
+    }
+
+    // Whether a and b can be joined across a's edge j, which is b's edge k walked backwards.
+    // Texture coordinates live on the face, so the shared corners have to agree on them or
+    // the texture would tear.
+    fn can_merge(a: &MeshFace, j: usize, b: &MeshFace, k: usize, min_dot: f32) -> bool {
+        let n = a.normal;
+        let m = b.normal;
+        let same_uvs = match (a.tex_coords.is_empty(), b.tex_coords.is_empty()) {
+            (true, true) => true,
+            (false, false) => {
+                a.tex_coords[j] == b.tex_coords[(k + 1) % b.tex_coords.len()]
+                    && a.tex_coords[(j + 1) % a.tex_coords.len()] == b.tex_coords[k]
+            }
+            _ => false,
+        };
+        a.color == b.color
+            && a.texture == b.texture
+            && a.flags == b.flags
+            && same_uvs
+            && n[0] * m[0] + n[1] * m[1] + n[2] * m[2] >= min_dot
+    }
+
+    // Join face b into face a across a's edge j, which is b's edge k walked backwards.
+    fn merge_faces(&self, a: &MeshFace, j: usize, b: &MeshFace, k: usize) -> MeshFace {
+        // Start a just after the shared edge so that it ends on the edge, then walk b from
+        // just after the shared edge, skipping the two shared vertices.
+        let la = a.indices.len();
+        let lb = b.indices.len();
+        let order = (0..la)
+            .map(|i| (a, (j + 1 + i) % la))
+            .chain((0..lb - 2).map(|i| (b, (k + 2 + i) % lb)));
+        let mut merged = a.clone();
+        merged.indices.clear();
+        merged.tex_coords.clear();
+        for (face, i) in order {
+            merged.indices.push(face.indices[i]);
+            if !face.tex_coords.is_empty() {
+                merged.tex_coords.push(face.tex_coords[i]);
+            }
+        }
+        merged.normal = merged.compute_normal(&self.positions);
+        merged
+    }
+
+    fn is_convex(&self, face: &MeshFace) -> bool {
+        let n = face.normal;
+        let count = face.indices.len();
+        for i in 0..count {
+            let p = self.positions[face.indices[i] as usize];
+            let q = self.positions[face.indices[(i + 1) % count] as usize];
+            let r = self.positions[face.indices[(i + 2) % count] as usize];
+            let u = [q[0] - p[0], q[1] - p[1], q[2] - p[2]];
+            let v = [r[0] - q[0], r[1] - q[1], r[2] - q[2]];
+            let c = [
+                u[1] * v[2] - u[2] * v[1],
+                u[2] * v[0] - u[0] * v[2],
+                u[0] * v[1] - u[1] * v[0],
+            ];
+            if c[0] * n[0] + c[1] * n[1] + c[2] * n[2] < -std::f32::EPSILON {
+                return false;
+            }
+        }
+        true
+    }
+
+    // Put faces that share an edge, and whose normals are within angle_tol radians of each
+    // other, into the same smoothing group. Groups are numbered from 1, in face order.
+    pub fn smoothing_groups(&self, angle_tol: f32) -> Vec<u32> {
+        let min_dot = angle_tol.cos();
+        let edges = Self::edge_map(&self.faces);
+        let mut groups = vec![0u32; self.faces.len()];
+        let mut next = 0;
+        for start in 0..self.faces.len() {
+            if groups[start] != 0 {
+                continue;
+            }
+            next += 1;
+            groups[start] = next;
+            let mut stack = vec![start];
+            while let Some(a) = stack.pop() {
+                let face = &self.faces[a];
+                for (j, &v0) in face.indices.iter().enumerate() {
+                    let v1 = face.indices[(j + 1) % face.indices.len()];
+                    // Look both ways, since FA does not wind faces consistently.
+                    for key in &[(v1, v0), (v0, v1)] {
+                        let b = match edges.get(key) {
+                            Some(&(b, _)) => b,
+                            None => continue,
+                        };
+                        let n = face.normal;
+                        let m = self.faces[b].normal;
+                        if groups[b] == 0 && n[0] * m[0] + n[1] * m[1] + n[2] * m[2] >= min_dot {
+                            groups[b] = next;
+                            stack.push(b);
+                        }
+                    }
+                }
+            }
+        }
+        groups
+    }
+
+    // Write the mesh as Wavefront OBJ, with faces gathered under an `s` statement for each
+    // smoothing group so that importers can rebuild smooth normals across curved surfaces.
+    // Only the vertices that faces use are written, renumbered from 1, along with their
+    // normals if any were filled in. Faces use the materials that to_mtl describes; since
+    // texture sizes are not known here, no texture coordinates are written.
+    pub fn to_obj(&self, angle_tol: f32) -> String {
+        let faces = (0..self.faces.len())
+            .filter(|&i| {
+                let face = &self.faces[i];
+                face.indices.len() >= 3
+                    && face
+                        .indices
+                        .iter()
+                        .all(|&index| (index as usize) < self.positions.len())
+            })
+            .collect::<Vec<_>>();
+        let groups = self.smoothing_groups(angle_tol);
+        let mut order = faces.clone();
+        order.sort_by_key(|&i| (groups[i], Self::obj_material(&self.faces[i])));
+
+        let mut remap = vec![None; self.positions.len()];
+        for &i in &faces {
+            for &index in &self.faces[i].indices {
+                remap[index as usize] = Some(0);
+            }
+        }
+        let mut out = String::new();
+        let mut next = 1;
+        for (i, slot) in remap.iter_mut().enumerate() {
+            if slot.is_some() {
+                *slot = Some(next);
+                next += 1;
+                let p = self.positions[i];
+                out += &format!("v {} {} {}\n", p[0], p[1], p[2]);
+            }
+        }
+        let have_normals = self.normals.iter().any(|n| *n != [0f32; 3]);
+        if have_normals {
+            for (i, slot) in remap.iter().enumerate() {
+                if slot.is_some() {
+                    let n = self.normals[i];
+                    out += &format!("vn {} {} {}\n", n[0], n[1], n[2]);
+                }
+            }
+        }
+
+        let mut current_group = 0;
+        let mut current_material = None;
+        for i in order {
+            let face = &self.faces[i];
+            if groups[i] != current_group {
+                current_group = groups[i];
+                current_material = None;
+                out += &format!("s {}\n", current_group);
+            }
+            let material = Self::obj_material(face);
+            if current_material.as_ref() != Some(&material) {
+                out += &format!("usemtl {}\n", material);
+                current_material = Some(material);
+            }
+            let indices = face
+                .indices
+                .iter()
+                .map(|&index| {
+                    let index = remap[index as usize].unwrap_or(0);
+                    if have_normals {
+                        format!("{}//{}", index, index)
+                    } else {
+                        index.to_string()
+                    }
+                })
+                .collect::<Vec<_>>();
+            out += &format!("f {}\n", indices.join(" "));
+        }
+        out
+    }
+
+    // The material library for to_obj. Materials are named after the PIC for textured faces
+    // and after the palette index for all others.
+    pub fn to_mtl(&self) -> String {
+        let materials = self
+            .faces
+            .iter()
+            .map(|face| (Self::obj_material(face), face.texture.is_some()))
+            .collect::<BTreeSet<_>>();
+        let mut out = String::new();
+        for (material, textured) in materials {
+            out += &format!("newmtl {}\n", material);
+            if textured {
+                out += &format!("map_Kd {}\n", material);
+            }
+        }
+        out
+    }
+
+    fn obj_material(face: &MeshFace) -> String {
+        match &face.texture {
+            Some(texture) => texture.to_owned(),
+            None => format!("color_{:02X}", face.color),
+        }
+    }
+
+    // Move all positions and normals from the shape's native axes into `convention`.
+    pub fn convert_to(&mut self, convention: CoordinateConvention) {
+        for p in self.positions.iter_mut().chain(self.normals.iter_mut()) {
+            *p = convention.transform(*p);
+        }
+        // Every convention is a rotation, so the handedness does not change.
+        for t in &mut self.tangents {
+            let [x, y, z] = convention.transform([t[0], t[1], t[2]]);
+            *t = [x, y, z, t[3]];
+        }
+        for face in &mut self.faces {
+            face.normal = convention.transform(face.normal);
+            face.material_normal = face.material_normal.map(|n| convention.transform(n));
+        }
+    }
+
+    // Give each vertex a single texture coordinate, copying the vertex wherever faces
+    // disagree about what it should be. Face indices are rewritten to point at the copies.
+    pub fn split_uv_seams(&mut self) {
+        let mut tex_coords = vec![None; self.positions.len()];
+        let mut copies: HashMap<(u32, [u16; 2]), u32> = HashMap::new();
+        for face in &mut self.faces {
+            if face.tex_coords.len() != face.indices.len() {
+                continue;
+            }
+            for (index, &uv) in face.indices.iter_mut().zip(&face.tex_coords) {
+                let base = *index as usize;
+                if base >= tex_coords.len() {
+                    continue;
+                }
+                let current = tex_coords[base];
+                match current {
+                    None => tex_coords[base] = Some(uv),
+                    Some(existing) if existing == uv => {}
+                    Some(_) => {
+                        let positions = &mut self.positions;
+                        let normals = &mut self.normals;
+                        let tangents = &mut self.tangents;
+                        let tex_coords = &mut tex_coords;
+                        *index = *copies.entry((*index, uv)).or_insert_with(|| {
+                            positions.push(positions[base]);
+                            normals.push(normals[base]);
+                            if !tangents.is_empty() {
+                                tangents.push(tangents[base]);
+                            }
+                            tex_coords.push(Some(uv));
+                            (positions.len() - 1) as u32
+                        });
+                    }
+                }
+            }
+        }
+        self.tex_coords = tex_coords;
+    }
+}
+
+fn is_ground_shadow(facet: &Facet, positions: &[[f32; 3]], ground: Option<f32>) -> bool {
+    let ground = match ground {
+        Some(ground) => ground,
+        None => return false,
+    };
+    facet.is_shadow()
+        && facet
+            .indices
+            .iter()
+            .all(|&i| match positions.get(i as usize) {
+                Some(p) => (p[2] - ground).abs() <= 1f32,
+                None => false,
+            })
+}
+
+// Gather the geometry that would be drawn for the normal, highest detail, model.
+pub fn build_mesh(sh: &RawShape, options: &MeshOptions) -> Fallible<MeshData> {
+    let mut positions: Vec<[f32; 3]> = Vec::new();
+    let mut explicit_normals: Vec<(usize, [f32; 3])> = Vec::new();
+    let mut faces = Vec::new();
+    let mut texture = None;
+
+    let mut section_close_byte_offset = None;
+    let mut damage_model_byte_offset = None;
+    let mut end_byte_offset = None;
+
+    // Shapes are z down, so the ground is at the largest z.
+    let ground = sh.geometry_bounds().map(|(_, hi)| f32::from(hi[2]));
+
+    let mut index = 0;
+    while index < sh.instrs.len() {
+        let at = sh.instrs[index].at_offset();
+        if Some(at) == damage_model_byte_offset || Some(at) == section_close_byte_offset {
+            match end_byte_offset {
+                Some(end) if end != at => {
+                    index = sh.bytes_to_index(end)?;
+                    continue;
+                }
+                _ => break,
+            }
+        }
+
+        match &sh.instrs[index] {
+            Instr::PtrToObjEnd(end) => end_byte_offset = Some(end.end_byte_offset()),
+            Instr::EndOfObject(_) => break,
+            Instr::JumpToDamage(_) | Instr::JumpToDetail(_) | Instr::JumpToLOD(_)
+                if options.base_only =>
+            {
+                break
+            }
+            Instr::JumpToDamage(dam) => damage_model_byte_offset = Some(dam.damage_byte_offset()),
+            Instr::JumpToDetail(detail) => {
+                section_close_byte_offset = Some(detail.target_byte_offset())
+            }
+            Instr::JumpToLOD(lod) => section_close_byte_offset = Some(lod.target_byte_offset()),
+            Instr::Jump(jump) => {
+                index = sh.bytes_to_index(jump.target_byte_offset())?;
+                continue;
+            }
+            Instr::JumpToFrame(frame) => {
+                // Only take the first frame of any animation.
+                index = sh.bytes_to_index(frame.target_for_frame(0))?;
+                continue;
+            }
+            Instr::TextureRef(tex) => texture = Some(tex.filename.to_uppercase()),
+            Instr::VertexBuf(buf) => {
+                positions.resize(buf.buffer_target_offset(), [0f32; 3]);
+                for v in buf.vertices() {
+                    positions.push([f32::from(v[0]), f32::from(v[1]), f32::from(v[2])]);
+                }
+            }
+            Instr::VertexNormal(vn) => {
+                let n = [
+                    f32::from(vn.norm[0]),
+                    f32::from(vn.norm[1]),
+                    f32::from(vn.norm[2]),
+                ];
+                explicit_normals.push((vn.index, normalize(n)));
+            }
+            Instr::Facet(facet) => {
+                if !(options.skip_shadows && is_ground_shadow(facet, &positions, ground)) {
+                    faces.push(MeshFace::from_facet(facet, &texture, &positions));
+                }
+            }
+            _ => {}
+        }
+        index += 1;
+    }
+
+    let mut mesh = MeshData::new(positions, faces);
+    if options.fix_winding {
+        mesh.fix_winding();
+    }
+    if options.compute_normals {
+        mesh.compute_smooth_normals();
+    }
+    for (index, normal) in explicit_normals {
+        if index < mesh.normals.len() {
+            mesh.normals[index] = normal;
+        } else {
+            trace!("skipping out-of-bounds vertex normal at {}", index);
+        }
+    }
+    if options.split_uv_seams {
+        mesh.split_uv_seams();
+    }
+    if options.compute_tangents {
+        mesh.compute_tangents();
+    }
+    mesh.convert_to(options.convention);
+    Ok(mesh)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lib::CatalogBuilder;
+
+    // An untextured face over the given corners, with its normal filled in.
+    fn make_face(indices: &[u32], positions: &[[f32; 3]]) -> MeshFace {
+        let mut face = MeshFace {
+            indices: indices.to_vec(),
+            tex_coords: Vec::new(),
+            texture: None,
+            color: 0,
+            flags: FacetFlags::empty(),
+            normal: [0f32; 3],
+            material_normal: None,
+        };
+        face.normal = face.compute_normal(positions);
+        face
+    }
+
+    fn make_cube() -> MeshData {
+        let positions = vec![
+            [-1f32, -1f32, -1f32],
+            [1f32, -1f32, -1f32],
+            [1f32, 1f32, -1f32],
+            [-1f32, 1f32, -1f32],
+            [-1f32, -1f32, 1f32],
+            [1f32, -1f32, 1f32],
+            [1f32, 1f32, 1f32],
+            [-1f32, 1f32, 1f32],
+        ];
+        let quads = [
+            [0, 3, 2, 1],
+            [4, 5, 6, 7],
+            [0, 1, 5, 4],
+            [2, 3, 7, 6],
+            [1, 2, 6, 5],
+            [0, 4, 7, 3],
+        ];
+        let faces = quads
+            .iter()
+            .map(|quad| make_face(quad, &positions))
+            .collect();
+        MeshData::new(positions, faces)
+    }
+
+    fn make_pair(apex: [f32; 3]) -> MeshData {
+        let positions = vec![
+            [0f32, 0f32, 0f32],
+            [1f32, 0f32, 0f32],
+            [1f32, 1f32, 0f32],
+            apex,
+        ];
+        let faces = vec![
+            make_face(&[0, 1, 2], &positions),
+            make_face(&[0, 2, 3], &positions),
+        ];
+        MeshData::new(positions, faces)
+    }
+
+    #[test]
+    fn it_can_merge_coplanar_faces() {
+        let mut quad = make_pair([0f32, 1f32, 0f32]);
+        quad.merge_coplanar(0.01);
+        assert_eq!(quad.faces.len(), 1);
+        let mut indices = quad.faces[0].indices.clone();
+        indices.sort();
+        assert_eq!(indices, vec![0, 1, 2, 3]);
+        assert!((quad.faces[0].normal[2] - 1f32).abs() < 0.0001);
+
+        let mut folded = make_pair([0f32, 1f32, 1f32]);
+        folded.merge_coplanar(0.01);
+        assert_eq!(folded.faces.len(), 2);
+
+        // The shared edge runs from 0 to 2; both faces have to put the same uv there.
+        let mut textured = make_pair([0f32, 1f32, 0f32]);
+        textured.faces[0].tex_coords = vec![[0, 0], [8, 0], [8, 8]];
+        textured.faces[1].tex_coords = vec![[0, 0], [8, 8], [0, 8]];
+        let mut torn = textured.clone();
+        torn.faces[1].tex_coords[1] = [4, 4];
+        textured.merge_coplanar(0.01);
+        assert_eq!(textured.faces.len(), 1);
+        assert_eq!(textured.faces[0].tex_coords.len(), 4);
+        torn.merge_coplanar(0.01);
+        assert_eq!(torn.faces.len(), 2);
+    }
+
+    #[test]
+    fn it_can_fix_winding() {
+        let mut mesh = make_cube();
+        let original = mesh.faces[0].normal;
+        mesh.faces[0].material_normal = Some([-original[0], -original[1], -original[2]]);
+        mesh.faces[1].material_normal = Some(mesh.faces[1].normal);
+        mesh.faces[0].tex_coords = vec![[0, 0], [1, 0], [1, 1], [0, 1]];
+        let indices0 = mesh.faces[0].indices.clone();
+        let indices1 = mesh.faces[1].indices.clone();
+
+        mesh.fix_winding();
+
+        let mut reversed = indices0;
+        reversed.reverse();
+        assert_eq!(mesh.faces[0].indices, reversed);
+        assert_eq!(
+            mesh.faces[0].tex_coords,
+            vec![[0, 1], [1, 1], [1, 0], [0, 0]]
+        );
+        for i in 0..3 {
+            assert!((mesh.faces[0].normal[i] + original[i]).abs() < 0.0001);
+        }
+        assert_eq!(mesh.faces[1].indices, indices1);
+    }
+
+    #[test]
+    fn it_can_convert_to_y_up() {
+        // A nose at -y, a right wingtip at +x, and a tail fin reaching up to -z.
+        let positions = vec![
+            [0f32, -10f32, 0f32],
+            [5f32, 2f32, 0f32],
+            [0f32, 4f32, -3f32],
+        ];
+        let face = make_face(&[0, 1, 2], &positions);
+        let mut mesh = MeshData::new(positions, vec![face]);
+        mesh.compute_smooth_normals();
+
+        mesh.convert_to(CoordinateConvention::YUp);
+        assert_eq!(mesh.positions[0], [0f32, 0f32, 10f32]);
+        assert_eq!(mesh.positions[1], [-5f32, 0f32, -2f32]);
+        assert_eq!(mesh.positions[2], [0f32, 3f32, -4f32]);
+
+        // The face normal is still what we would compute from the converted positions.
+        let expect = mesh.faces[0].compute_normal(&mesh.positions);
+        for i in 0..3 {
+            assert!((mesh.faces[0].normal[i] - expect[i]).abs() < 0.0001);
+            assert!((mesh.normals[0][i] - expect[i]).abs() < 0.0001);
+        }
+    }
+
+    #[test]
+    fn it_can_compute_tangents() {
+        let positions = vec![
+            [0f32, 0f32, 0f32],
+            [2f32, 0f32, 0f32],
+            [2f32, 1f32, 0.1f32],
+            [0f32, 1f32, 0.1f32],
+        ];
+        let mut face = make_face(&[0, 1, 2, 3], &positions);
+        face.tex_coords = vec![[0, 0], [64, 0], [64, 32], [0, 32]];
+        face.texture = Some("QUAD.PIC".to_owned());
+        face.flags = FacetFlags::HAVE_TEXCOORDS;
+        let mut mesh = MeshData::new(positions, vec![face]);
+        mesh.compute_smooth_normals();
+        mesh.compute_tangents();
+
+        assert_eq!(mesh.tangents.len(), 4);
+        for (t, n) in mesh.tangents.iter().zip(&mesh.normals) {
+            let t3 = [t[0], t[1], t[2]];
+            assert!((dot(t3, t3).sqrt() - 1f32).abs() < 0.0001);
+            assert!(dot(t3, *n).abs() < 0.001);
+            // u increases along +x.
+            assert!(t[0] > 0.99);
+            assert_eq!(t[3].abs(), 1f32);
+        }
+    }
+
+    #[test]
+    fn it_can_split_uv_seams() {
+        let mut mesh = make_cube();
+        mesh.compute_smooth_normals();
+        for (i, face) in mesh.faces.iter_mut().enumerate() {
+            face.tex_coords = vec![[i as u16, 0]; face.indices.len()];
+        }
+        mesh.faces.truncate(2);
+
+        // The first two faces of the cube share no vertices, so only give them a shared one.
+        mesh.faces[1].indices[0] = mesh.faces[0].indices[0];
+        let shared = mesh.faces[0].indices[0];
+        mesh.split_uv_seams();
+
+        assert_eq!(mesh.positions.len(), 9);
+        assert_eq!(mesh.normals.len(), 9);
+        assert_eq!(mesh.tex_coords.len(), 9);
+        assert_eq!(mesh.faces[0].indices[0], shared);
+        assert_eq!(mesh.faces[1].indices[0], 8);
+        assert_eq!(mesh.positions[8], mesh.positions[shared as usize]);
+        assert_eq!(mesh.normals[8], mesh.normals[shared as usize]);
+        assert_eq!(mesh.tex_coords[shared as usize], Some([0, 0]));
+        assert_eq!(mesh.tex_coords[8], Some([1, 0]));
+    }
+
+    #[test]
+    fn it_can_smooth_a_welded_cube() {
+        let mut mesh = make_cube();
+
+        let mut face_normals = mesh
+            .faces
+            .iter()
+            .map(|f| {
+                let n = f.normal;
+                (
+                    n[0].round() as i32,
+                    n[1].round() as i32,
+                    n[2].round() as i32,
+                )
+            })
+            .collect::<Vec<_>>();
+        face_normals.sort();
+        face_normals.dedup();
+        assert_eq!(face_normals.len(), 6);
+        assert!(mesh.normals.iter().all(|n| *n == [0f32; 3]));
+
+        mesh.compute_smooth_normals();
+        let expect = 1f32 / 3f32.sqrt();
+        for (p, n) in mesh.positions.iter().zip(&mesh.normals) {
+            for i in 0..3 {
+                assert!((n[i] - p[i] * expect).abs() < 0.0001);
+            }
+        }
+    }
+
+    #[test]
+    fn it_can_export_smoothing_groups() {
+        let count_groups = |obj: &str| obj.lines().filter(|l| l.starts_with("s ")).count();
+
+        let flat = make_pair([0f32, 1f32, 0f32]);
+        let obj = flat.to_obj(0.5);
+        assert_eq!(count_groups(&obj), 1);
+        assert_eq!(obj.lines().filter(|l| l.starts_with("v ")).count(), 4);
+        assert!(obj.contains("f 1 2 3\n"));
+
+        let cube = make_cube();
+        assert_eq!(count_groups(&cube.to_obj(0.5)), 6);
+        assert_eq!(count_groups(&cube.to_obj(2f32)), 1);
+    }
+
+    #[test]
+    fn it_can_skip_ground_shadows() -> Fallible<()> {
+        // An untextured quad on the ground, and a textured one lifted above it.
+        let shadow = [0xFC, 0, 0, 0x5B, 0, 4, 0, 1, 2, 3];
+        let body = [
+            0xFC, 0x04, 0x01, 0x5B, 0, 4, 4, 5, 6, 7, 0, 0, 9, 0, 9, 9, 0, 9,
+        ];
+        let shadow = Facet::from_bytes_after(0, &shadow)?;
+        let body = Facet::from_bytes_after(0, &body)?;
+        assert!(shadow.is_shadow());
+        assert!(!body.is_shadow());
+        let mut positions = Vec::new();
+        for &z in &[10f32, 0f32] {
+            positions.extend_from_slice(&[[0f32, 0f32, z], [1f32, 0f32, z], [1f32, 1f32, z]]);
+            positions.push([0f32, 1f32, z]);
+        }
+        assert!(is_ground_shadow(&shadow, &positions, Some(10f32)));
+        assert!(!is_ground_shadow(&shadow, &positions, Some(20f32)));
+        assert!(!is_ground_shadow(&body, &positions, Some(0f32)));
+
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["FA:F18.SH".to_owned()])?;
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let sh = RawShape::from_bytes(&catalog.read_sync(fid)?)?;
+            let with = build_mesh(&sh, &MeshOptions::default())?;
+            let without = build_mesh(
+                &sh,
+                &MeshOptions {
+                    skip_shadows: true,
+                    ..Default::default()
+                },
+            )?;
+
+            // The textured body is never mistaken for a shadow.
+            let textured =
+                |mesh: &MeshData| mesh.faces.iter().filter(|f| f.texture.is_some()).count();
+            assert!(textured(&without) > 0);
+            assert_eq!(textured(&with), textured(&without));
+            assert!(without.faces.len() <= with.faces.len());
+        }
+        Ok(())
+    }
+}
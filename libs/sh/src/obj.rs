@@ -0,0 +1,107 @@
+// This file is part of OpenFA.
+//
+// OpenFA is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// OpenFA is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
+use crate::{
+    mesh::{build_mesh, MeshOptions},
+    Instr, RawShape,
+};
+use failure::Fallible;
+
+// Which of the shape's models to export.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DetailSelector {
+    // Only the geometry drawn before the first JumpToDetail, JumpToLOD, or JumpToDamage.
+    BaseOnly,
+
+    // The normal, highest detail, model, skipping over the other levels and the damage model
+    // the same way that build_mesh does.
+    Highest,
+}
+
+// How far apart, in radians, the normals of neighboring faces can be while still sharing a
+// smoothing group in the export.
+const SMOOTHING_ANGLE: f32 = 0.5;
+
+impl RawShape {
+    // Write one model of the shape as Wavefront OBJ, returning the OBJ and MTL text. See
+    // MeshData::to_obj for what gets written.
+    pub fn export_obj(&self, lod: DetailSelector) -> Fallible<(String, String)> {
+        let mesh = build_mesh(self, &self.obj_mesh_options(lod))?;
+        Ok((mesh.to_obj(SMOOTHING_ANGLE), mesh.to_mtl()))
+    }
+
+    fn obj_mesh_options(&self, lod: DetailSelector) -> MeshOptions {
+        let have_normals = self
+            .instrs
+            .iter()
+            .any(|instr| matches!(instr, Instr::VertexNormal(_)));
+        MeshOptions {
+            // Fill in the vertices that VertexNormal does not cover.
+            compute_normals: have_normals,
+            fix_winding: true,
+            base_only: lod == DetailSelector::BaseOnly,
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use lib::CatalogBuilder;
+
+    #[test]
+    fn it_can_export_obj() -> Fallible<()> {
+        let (catalog, inputs) = CatalogBuilder::build_and_select(&["FA:F18.SH".to_owned()])?;
+        for &fid in &inputs {
+            let shape = RawShape::from_bytes(&catalog.read_sync(fid)?)?;
+            let (obj, mtl) = shape.export_obj(DetailSelector::BaseOnly)?;
+            let vertex_count = obj.lines().filter(|l| l.starts_with("v ")).count();
+            let normal_count = obj.lines().filter(|l| l.starts_with("vn ")).count();
+            assert!(vertex_count > 0);
+            assert!(normal_count == 0 || normal_count == vertex_count);
+            for line in obj.lines().filter(|l| l.starts_with("f ")) {
+                let indices = line.split_whitespace().skip(1).collect::<Vec<_>>();
+                assert!(indices.len() >= 3);
+                for index in indices {
+                    let v = index.split('/').next().unwrap().parse::<usize>()?;
+                    assert!(v >= 1 && v <= vertex_count);
+                }
+            }
+            for line in obj.lines().filter(|l| l.starts_with("usemtl ")) {
+                assert!(mtl.contains(&format!("newmtl {}\n", &line[7..])));
+            }
+
+            // The base model stops at the first branch, so its faces lead off the full model.
+            let base = build_mesh(&shape, &shape.obj_mesh_options(DetailSelector::BaseOnly))?;
+            let full = build_mesh(&shape, &shape.obj_mesh_options(DetailSelector::Highest))?;
+            let face_count = obj.lines().filter(|l| l.starts_with("f ")).count();
+            assert_eq!(face_count, base.faces.len());
+            assert!(shape.instrs.iter().any(|instr| matches!(
+                instr,
+                Instr::JumpToDetail(_) | Instr::JumpToLOD(_) | Instr::JumpToDamage(_)
+            )));
+            assert!(base.faces.len() < full.faces.len());
+            for (a, b) in base.faces.iter().zip(&full.faces) {
+                assert_eq!(a.indices, b.indices);
+                assert_eq!(a.color, b.color);
+            }
+
+            let (full_obj, _) = shape.export_obj(DetailSelector::Highest)?;
+            let full_count = full_obj.lines().filter(|l| l.starts_with("f ")).count();
+            assert_eq!(full_count, full.faces.len());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,326 @@
+// This file is part of OpenFA.
+//
+// OpenFA is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// OpenFA is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
+use crate::{Instr, RawShape, X86Code};
+use failure::Fallible;
+use i386::{ExitInfo, Interpreter, ObjectFieldMap};
+use std::collections::HashMap;
+
+// Where we pretend the object that owns the shape lives, as brentObjId points to it. Any
+// field that the caller does not give us reads as zero.
+const OBJECT_BASE: u32 = 0x60000;
+const OBJECT_SIZE: usize = 0x400;
+
+// What the shape's code decided at one tick.
+#[derive(Clone, Debug)]
+pub struct FrameState {
+    pub tick: u32,
+
+    // For each x86 block in the shape, in order, the index of the instruction that the
+    // block handed drawing back to with do_start_interp. None if the block stopped anywhere
+    // else, e.g. in a call into the engine that we cannot follow.
+    pub resume_at: Vec<Option<usize>>,
+
+    // The object's fields after every block has run, with anything the code wrote to them.
+    pub object: ObjectFieldMap,
+}
+
+// The player state that the engine hands to a shape's code through its data trampolines.
+// Positions run from 0 to 8192, in the game's angle units; the rest are 0 or 1. Ports that a
+// shape reads but that are not covered here read as zero.
+#[derive(Clone, Debug, Default)]
+pub struct AnimationInputs {
+    pub gear_position: u32,
+    pub gear_down: u32,
+    pub bay_position: u32,
+    pub bay_open: u32,
+    pub canard_position: u32,
+    pub afterburner: u32,
+    pub vertical_on: u32,
+    pub vertical_angle: u32,
+    pub swing_wing: u32,
+}
+
+impl AnimationInputs {
+    fn value_for(&self, name: &str) -> u32 {
+        match name {
+            "_PLgearPos" => self.gear_position,
+            "_PLgearDown" => self.gear_down,
+            "_PLbayDoorPos" => self.bay_position,
+            "_PLbayOpen" => self.bay_open,
+            "_PLcanardPos" => self.canard_position,
+            "_PLafterBurner" => self.afterburner,
+            "_PLvtOn" => self.vertical_on,
+            "_PLvtAngle" => self.vertical_angle,
+            "_PLswingWing" => self.swing_wing,
+            _ => 0,
+        }
+    }
+}
+
+// What one x86 block decided, given the tick and inputs.
+#[derive(Clone, Debug)]
+pub struct AnimationState {
+    // The index of the X86Code instruction.
+    pub code_index: usize,
+
+    // The instruction the block handed drawing back to with do_start_interp, if it got there.
+    pub resume_at: Option<usize>,
+
+    // If the block is followed by an XformUnmask, the t0, t1, t2, a0, a1, a2 that the block
+    // wrote into it.
+    pub xform: Option<[i16; 6]>,
+
+    // If resume_at is one of the frames of a JumpToFrame: the JumpToFrame's index and the
+    // frame number.
+    pub frame: Option<(usize, usize)>,
+}
+
+impl RawShape {
+    // Run every x86 block in the shape once per tick, with _currentTicks set to the tick and
+    // brentObjId pointing at the given object. Since the blocks pick which instructions get
+    // drawn next, the states are enough to bake the shape's animation. Every block starts
+    // from a clean machine, so nothing carries over between blocks or ticks.
+    pub fn simulate(&self, ticks: &[u32], object: &ObjectFieldMap) -> Fallible<Vec<FrameState>> {
+        let blocks = self.x86_blocks().map(|(_, x86)| x86).collect::<Vec<_>>();
+        let (base, ticks_location) = self.prepare_interpreter(&AnimationInputs::default())?;
+
+        let mut states = Vec::new();
+        for &tick in ticks {
+            let mut fields = object.to_owned();
+            let mut resume_at = Vec::new();
+            for x86 in &blocks {
+                let mut vm = base.clone();
+                if let Some(location) = ticks_location {
+                    vm.map_value(location, tick);
+                }
+                vm.bind_object(OBJECT_BASE, &fields);
+                resume_at.push(self.run_block(&mut vm, x86));
+                vm.unbind_object(OBJECT_BASE, &mut fields)?;
+            }
+            states.push(FrameState {
+                tick,
+                resume_at,
+                object: fields,
+            });
+        }
+        Ok(states)
+    }
+
+    // Run every x86 block once at the given tick, with the player state in inputs, and
+    // report what each one picked: where drawing resumes, the transform it wrote into a
+    // following XformUnmask, and the animation frame that it lands on, if any. This is what
+    // the renderer does each frame to animate gear, bays, afterburners, and spinners.
+    pub fn evaluate_animation(
+        &self,
+        ticks: u32,
+        inputs: &AnimationInputs,
+    ) -> Fallible<Vec<AnimationState>> {
+        let (mut base, ticks_location) = self.prepare_interpreter(inputs)?;
+        if let Some(location) = ticks_location {
+            base.map_value(location, ticks);
+        }
+
+        let mut frames = HashMap::new();
+        for (index, instr) in self.instrs.iter().enumerate() {
+            if let Instr::JumpToFrame(jump) = instr {
+                for n in 0..jump.num_frames() {
+                    frames.insert(jump.target_for_frame(n), (index, n));
+                }
+            }
+        }
+
+        let mut states = Vec::new();
+        for (code_index, x86) in self.x86_blocks() {
+            let mut vm = base.clone();
+            // The block writes the transform in place, just after the XformUnmask's magic.
+            let xform_slot = match self.instrs.get(code_index + 1) {
+                Some(Instr::XformUnmask(xform)) => Some((xform.offset, xform.xform_base)),
+                Some(Instr::XformUnmask4(xform)) => Some((xform.offset, xform.xform_base)),
+                _ => None,
+            }
+            .map(|(offset, base)| (self.instr_base_address() + offset as u32 + 2, base));
+            if let Some((addr, base)) = xform_slot {
+                vm.map_writable(addr, base.to_vec())?;
+            }
+            let resume_at = self.run_block(&mut vm, x86);
+            let xform = match xform_slot {
+                Some((addr, _)) => {
+                    let words = vm.unmap_writable(addr)?;
+                    let mut xform = [0i16; 6];
+                    for (i, v) in xform.iter_mut().enumerate() {
+                        *v = i16::from_le_bytes([words[i * 2], words[i * 2 + 1]]);
+                    }
+                    Some(xform)
+                }
+                None => None,
+            };
+            let frame =
+                resume_at.and_then(|index| frames.get(&self.instrs[index].at_offset()).cloned());
+            states.push(AnimationState {
+                code_index,
+                resume_at,
+                xform,
+                frame,
+            });
+        }
+        Ok(states)
+    }
+
+    fn x86_blocks(&self) -> impl Iterator<Item = (usize, &X86Code)> {
+        self.instrs
+            .iter()
+            .enumerate()
+            .filter_map(|(i, instr)| match instr {
+                Instr::X86Code(x86) => Some((i, x86)),
+                _ => None,
+            })
+    }
+
+    // A machine with every x86 block loaded, brentObjId pointing at a zeroed object, and the
+    // data trampolines set from inputs. _currentTicks is left for the caller to set at the
+    // returned location, if the shape reads it.
+    fn prepare_interpreter(
+        &self,
+        inputs: &AnimationInputs,
+    ) -> Fallible<(Interpreter, Option<u32>)> {
+        let mut vm = Interpreter::new();
+        vm.map_writable(OBJECT_BASE, vec![0u8; OBJECT_SIZE])?;
+        for (_, x86) in self.x86_blocks() {
+            vm.add_code(x86.bytecode.clone());
+        }
+        let mut ticks_location = None;
+        for tramp in &self.trampolines {
+            if !tramp.is_data {
+                let arg_count = if tramp.name == "do_start_interp" {
+                    1
+                } else {
+                    0
+                };
+                vm.add_trampoline(tramp.mem_location, &tramp.name, arg_count);
+            } else if tramp.name == "brentObjId" {
+                vm.map_value(tramp.mem_location, OBJECT_BASE);
+            } else if tramp.name == "_currentTicks" {
+                ticks_location = Some(tramp.mem_location);
+            } else {
+                vm.map_value(tramp.mem_location, inputs.value_for(&tramp.name));
+            }
+        }
+        Ok((vm, ticks_location))
+    }
+
+    // Run one block and return the instruction it hands drawing back to.
+    fn run_block(&self, vm: &mut Interpreter, x86: &X86Code) -> Option<usize> {
+        match vm.interpret(x86.code_offset(self.instr_base_address())) {
+            Ok(ExitInfo::Trampoline(ref name, ref args))
+                if name == "do_start_interp" && args.len() == 1 =>
+            {
+                self.map_interpreter_offset_to_instr_offset(args[0]).ok()
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use lib::CatalogBuilder;
+
+    #[test]
+    fn it_can_simulate_an_effect() -> Fallible<()> {
+        let (catalog, inputs) = CatalogBuilder::build_and_select(&["FA:EXP.SH".to_owned()])?;
+        for &fid in &inputs {
+            let shape = RawShape::from_bytes(&catalog.read_sync(fid)?)?;
+            let blocks = shape
+                .instrs
+                .iter()
+                .filter(|instr| matches!(instr, Instr::X86Code(_)))
+                .count();
+            let ticks = [0, 16, 64, 256, 1024, 4095];
+            let states = shape.simulate(&ticks, &ObjectFieldMap::new())?;
+            assert_eq!(states.len(), ticks.len());
+            for (state, &tick) in states.iter().zip(&ticks) {
+                assert_eq!(state.tick, tick);
+                assert_eq!(state.resume_at.len(), blocks);
+                assert!(state.resume_at.iter().any(Option::is_some));
+            }
+            assert!(states
+                .iter()
+                .any(|state| state.resume_at != states[0].resume_at));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_can_run_an_effect_with_a_bound_object() -> Fallible<()> {
+        let (catalog, inputs) = CatalogBuilder::build_and_select(&["FA:EXP.SH".to_owned()])?;
+        for &fid in &inputs {
+            let shape = RawShape::from_bytes(&catalog.read_sync(fid)?)?;
+            let (base, ticks_location) = shape.prepare_interpreter(&AnimationInputs::default())?;
+            let mut exp = ObjectFieldMap::new()
+                .with_field("field40", 0x40, 0)
+                .with_field("field48", 0x48, 0);
+            let mut returned = 0;
+            for (_, x86) in shape.x86_blocks() {
+                let mut vm = base.clone();
+                if let Some(location) = ticks_location {
+                    vm.map_value(location, 256);
+                }
+                vm.bind_object(OBJECT_BASE, &exp);
+                let exit = vm.interpret(x86.code_offset(shape.instr_base_address()));
+                if let Ok(ExitInfo::Trampoline(name, args)) = exit {
+                    if name == "do_start_interp" {
+                        assert_eq!(args.len(), 1);
+                        assert!(shape
+                            .map_interpreter_offset_to_instr_offset(args[0])
+                            .is_ok());
+                        returned += 1;
+                    }
+                }
+                vm.unbind_object(OBJECT_BASE, &mut exp)?;
+            }
+            assert!(returned > 0);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_can_evaluate_gear_animation() -> Fallible<()> {
+        let (catalog, inputs) = CatalogBuilder::build_and_select(&["FA:F18.SH".to_owned()])?;
+        for &fid in &inputs {
+            let shape = RawShape::from_bytes(&catalog.read_sync(fid)?)?;
+            let down = AnimationInputs {
+                gear_down: 1,
+                gear_position: 0,
+                ..Default::default()
+            };
+            let up = AnimationInputs {
+                gear_down: 0,
+                gear_position: 8192,
+                ..Default::default()
+            };
+            let down = shape.evaluate_animation(0, &down)?;
+            let up = shape.evaluate_animation(0, &up)?;
+            assert_eq!(down.len(), shape.x86_blocks().count());
+            assert_eq!(down.len(), up.len());
+            assert!(down.iter().any(|state| state.xform.is_some()));
+            assert!(down
+                .iter()
+                .zip(&up)
+                .any(|(down, up)| down.xform != up.xform));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,60 @@
+// This file is part of OpenFA.
+//
+// OpenFA is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// OpenFA is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
+use crate::{source_file, Instr, RawShape};
+use catalog::Catalog;
+use failure::Fallible;
+use std::collections::{BTreeMap, BTreeSet};
+
+// Map every asm source named by a SourceRef in the catalog's shapes to the shapes that name
+// it. Sources are keyed by lower-cased file name, e.g. exp.asm, since some shapes carry a
+// full path and others do not. This is the closest thing we have to a listing of the
+// programs that Jane's built the shapes from.
+pub fn source_library(catalog: &Catalog) -> Fallible<BTreeMap<String, BTreeSet<String>>> {
+    let mut library = BTreeMap::new();
+    for name in catalog.find_matching_names("*.SH")?.iter() {
+        let name = name.to_string();
+        let shape = RawShape::from_bytes(&catalog.read_name_sync(&name)?)?;
+        for instr in &shape.instrs {
+            if let Instr::SourceRef(source) = instr {
+                library
+                    .entry(source_file(&source.source))
+                    .or_insert_with(BTreeSet::new)
+                    .insert(name.clone());
+            }
+        }
+    }
+    Ok(library)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use lib::CatalogBuilder;
+
+    #[test]
+    fn it_can_list_shape_sources() -> Fallible<()> {
+        let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["FA:EXP.SH".to_owned()])?;
+        for &fid in &inputs {
+            let label = catalog.file_label(fid)?;
+            catalog.set_default_label(&label);
+            let library = source_library(&catalog)?;
+            assert!(library["exp.asm"].contains("EXP.SH"));
+            for shapes in library.values() {
+                assert!(!shapes.is_empty());
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,237 @@
+// This file is part of OpenFA.
+//
+// OpenFA is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// OpenFA is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with OpenFA.  If not, see <http://www.gnu.org/licenses/>.
+use crate::mesh::{CoordinateConvention, MeshData};
+use image::{Rgba, RgbaImage};
+use pal::Palette;
+
+// Leave a little room around the shape so that it does not touch the frame.
+const MARGIN: f32 = 0.05;
+
+// Fixed light for flat shading, pointing roughly from over the viewer's shoulder.
+const LIGHT: [f32; 3] = [0.3, 0.8, -0.5];
+
+// Rotate a point from the shape's native axes into a 3/4 view of the front: up into y,
+// then 135 degrees around the vertical so that the nose points at us and off to one side,
+// then tipped 30 degrees towards the camera so that we can see the top of the shape. The
+// camera looks down +z.
+fn view_transform(p: [f32; 3]) -> [f32; 3] {
+    let p = CoordinateConvention::YUp.transform(p);
+    let (sy, cy) = (3f32 * std::f32::consts::FRAC_PI_4).sin_cos();
+    let (sp, cp) = (std::f32::consts::PI / 6f32).sin_cos();
+    let x = p[0] * cy - p[2] * sy;
+    let z = p[0] * sy + p[2] * cy;
+    let y = p[1] * cp - z * sp;
+    let z = p[1] * sp + z * cp;
+    [x, y, z]
+}
+
+fn edge(a: [f32; 3], b: [f32; 3], x: f32, y: f32) -> f32 {
+    (b[0] - a[0]) * (y - a[1]) - (b[1] - a[1]) * (x - a[0])
+}
+
+impl MeshData {
+    // Software rasterize the mesh into a small, square, flat-shaded image with a transparent
+    // background. This is meant for browsing shapes, not for anything that needs to match
+    // what we draw in game. The mesh must be in its native axes, as build_mesh gives it by
+    // default.
+    pub fn render_thumbnail(&self, palette: &Palette, size: u32) -> RgbaImage {
+        let mut img = RgbaImage::new(size, size);
+        if self.positions.is_empty() || size == 0 {
+            return img;
+        }
+
+        let view = self
+            .positions
+            .iter()
+            .map(|&p| view_transform(p))
+            .collect::<Vec<_>>();
+        let mut lo = [std::f32::MAX; 2];
+        let mut hi = [std::f32::MIN; 2];
+        for p in &view {
+            for i in 0..2 {
+                lo[i] = lo[i].min(p[i]);
+                hi[i] = hi[i].max(p[i]);
+            }
+        }
+        let extent = (hi[0] - lo[0]).max(hi[1] - lo[1]).max(std::f32::EPSILON);
+        let scale = size as f32 * (1f32 - 2f32 * MARGIN) / extent;
+        let center = [(lo[0] + hi[0]) / 2f32, (lo[1] + hi[1]) / 2f32];
+        let half = size as f32 / 2f32;
+        let screen = view
+            .iter()
+            .map(|p| {
+                [
+                    half + (p[0] - center[0]) * scale,
+                    half - (p[1] - center[1]) * scale,
+                    p[2],
+                ]
+            })
+            .collect::<Vec<_>>();
+
+        let light_len = (LIGHT[0] * LIGHT[0] + LIGHT[1] * LIGHT[1] + LIGHT[2] * LIGHT[2]).sqrt();
+        let mut depth = vec![std::f32::MAX; (size * size) as usize];
+        for face in &self.faces {
+            if face.indices.len() < 3 || face.indices.iter().any(|&i| i as usize >= screen.len()) {
+                continue;
+            }
+
+            let n = view_transform(face.normal);
+            let lambert = (n[0] * LIGHT[0] + n[1] * LIGHT[1] + n[2] * LIGHT[2]).abs() / light_len;
+            let shade = 0.35 + 0.65 * lambert;
+            let base = palette.rgba(face.color as usize).unwrap_or(Rgba {
+                data: [255, 0, 255, 255],
+            });
+            let color = Rgba {
+                data: [
+                    (f32::from(base.data[0]) * shade) as u8,
+                    (f32::from(base.data[1]) * shade) as u8,
+                    (f32::from(base.data[2]) * shade) as u8,
+                    255,
+                ],
+            };
+
+            // Fan out the polygon into triangles.
+            let a = screen[face.indices[0] as usize];
+            for pair in face.indices[1..].windows(2) {
+                let b = screen[pair[0] as usize];
+                let c = screen[pair[1] as usize];
+                let area = edge(a, b, c[0], c[1]);
+                if area.abs() < std::f32::EPSILON {
+                    continue;
+                }
+                let x0 = a[0].min(b[0]).min(c[0]).floor().max(0f32) as u32;
+                let y0 = a[1].min(b[1]).min(c[1]).floor().max(0f32) as u32;
+                let x1 = (a[0].max(b[0]).max(c[0]).ceil() as u32).min(size - 1);
+                let y1 = (a[1].max(b[1]).max(c[1]).ceil() as u32).min(size - 1);
+                for y in y0..=y1 {
+                    for x in x0..=x1 {
+                        let px = x as f32 + 0.5;
+                        let py = y as f32 + 0.5;
+                        let w0 = edge(b, c, px, py) / area;
+                        let w1 = edge(c, a, px, py) / area;
+                        let w2 = edge(a, b, px, py) / area;
+                        if w0 < 0f32 || w1 < 0f32 || w2 < 0f32 {
+                            continue;
+                        }
+                        let z = w0 * a[2] + w1 * b[2] + w2 * c[2];
+                        let offset = (y * size + x) as usize;
+                        if z < depth[offset] {
+                            depth[offset] = z;
+                            img.put_pixel(x, y, color);
+                        }
+                    }
+                }
+            }
+        }
+        img
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{mesh::MeshFace, FacetFlags};
+
+    fn make_mesh(positions: Vec<[f32; 3]>, quads: &[[u32; 4]]) -> MeshData {
+        let mut faces = Vec::new();
+        for quad in quads {
+            let mut face = MeshFace {
+                indices: quad.to_vec(),
+                tex_coords: Vec::new(),
+                texture: None,
+                color: 1,
+                flags: FacetFlags::empty(),
+                normal: [0f32; 3],
+                material_normal: None,
+            };
+            face.normal = face.compute_normal(&positions);
+            faces.push(face);
+        }
+        MeshData::new(positions, faces)
+    }
+
+    #[test]
+    fn it_views_shapes_from_the_front_and_above() {
+        // Native z is down, so -z is up and should land near the screen's vertical.
+        let up = view_transform([0f32, 0f32, -1f32]);
+        assert!(up[0].abs() < 0.001);
+        assert!(up[1] > 0.8);
+        // The nose is at -y and should point towards the camera.
+        let nose = view_transform([0f32, -1f32, 0f32]);
+        assert!(nose[2] < 0f32);
+
+        // A tall, thin tail fin should stand up in the thumbnail.
+        let positions = vec![
+            [0f32, 0.9f32, 0f32],
+            [0f32, 1.1f32, 0f32],
+            [0f32, 1.1f32, -2f32],
+            [0f32, 0.9f32, -2f32],
+        ];
+        let mesh = make_mesh(positions, &[[0, 1, 2, 3]]);
+        let palette = Palette::grayscale().unwrap();
+        let img = mesh.render_thumbnail(&palette, 64);
+        let mut lo = [std::u32::MAX; 2];
+        let mut hi = [0u32; 2];
+        for (x, y, pixel) in img.enumerate_pixels() {
+            if pixel.data[3] != 0 {
+                lo = [lo[0].min(x), lo[1].min(y)];
+                hi = [hi[0].max(x), hi[1].max(y)];
+            }
+        }
+        assert!(hi[1] - lo[1] > 2 * (hi[0] - lo[0]));
+    }
+
+    #[test]
+    fn it_can_render_a_centered_thumbnail() {
+        let positions = vec![
+            [-1f32, -1f32, -1f32],
+            [1f32, -1f32, -1f32],
+            [1f32, 1f32, -1f32],
+            [-1f32, 1f32, -1f32],
+            [-1f32, -1f32, 1f32],
+            [1f32, -1f32, 1f32],
+            [1f32, 1f32, 1f32],
+            [-1f32, 1f32, 1f32],
+        ];
+        let quads = [
+            [0, 3, 2, 1],
+            [4, 5, 6, 7],
+            [0, 1, 5, 4],
+            [2, 3, 7, 6],
+            [1, 2, 6, 5],
+            [0, 4, 7, 3],
+        ];
+        let mesh = make_mesh(positions, &quads);
+
+        let palette = Palette::grayscale().unwrap();
+        let img = mesh.render_thumbnail(&palette, 64);
+        assert_eq!(img.dimensions(), (64, 64));
+
+        let mut count = 0u32;
+        let mut sum = [0u32; 2];
+        for (x, y, pixel) in img.enumerate_pixels() {
+            if pixel.data[3] != 0 {
+                count += 1;
+                sum[0] += x;
+                sum[1] += y;
+            }
+        }
+        assert!(count > 64 * 64 / 4);
+        let cx = sum[0] as f32 / count as f32;
+        let cy = sum[1] as f32 / count as f32;
+        assert!((cx - 32f32).abs() < 4f32);
+        assert!((cy - 32f32).abs() < 4f32);
+    }
+}
@@ -188,9 +188,50 @@ impl Sample {
     }
 }
 
+// Which of the three header layouts a T2 was stored with.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum T2Format {
+    // BITE, as shipped with USNF: no name field.
+    Bite0,
+    // BITE, from later games, with a name before the pic file.
+    Bite1,
+    // BIT2
+    Bit2,
+}
+
+// Everything in a T2 other than the samples, for tools that want to list terrains.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TerrainMetadata {
+    pub name: String,
+    pub pic_file: String,
+    pub format: T2Format,
+    pub width: u32,
+    pub height: u32,
+    pub width_ft: f32,
+    pub height_ft: f32,
+    // Lat/Lon of the map's base corner; see MAP_POSITIONS.
+    pub origin_latitude: f32,
+    pub origin_longitude: f32,
+}
+
+// A flat-shaded quad mesh built from the samples, in feet, with x east and z north from
+// the origin. Renderers convert this into whatever they draw with.
+#[derive(Clone, Debug, Default)]
+pub struct TerrainMesh {
+    pub positions: Vec<[f32; 3]>,
+    pub quads: Vec<TerrainQuad>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TerrainQuad {
+    pub indices: [u32; 4],
+    pub color: u8,
+}
+
 pub struct Terrain {
     name: String,
-    _pic_file: String,
+    pic_file: String,
+    format: T2Format,
     width: u32,
     height: u32,
     width_ft: f32,
@@ -353,7 +394,8 @@ impl Terrain {
 
         let terrain = Terrain {
             name,
-            _pic_file: pic_file,
+            pic_file,
+            format: T2Format::Bite0,
             width_ft: ((header.width_ft() as u32) << 8) as f32,
             height_ft: ((header.height_ft() as u32) << 8) as f32,
             width: header.width() as u32,
@@ -484,7 +526,8 @@ impl Terrain {
 
         let terrain = Terrain {
             name,
-            _pic_file: pic_file,
+            pic_file,
+            format: T2Format::Bite1,
             width_ft: ((header.width_ft() as u32) << 8) as f32,
             height_ft: ((header.height_ft() as u32) << 8) as f32,
             width: header.width() as u32,
@@ -579,7 +622,8 @@ impl Terrain {
 
         let terrain = Terrain {
             name,
-            _pic_file: pic_file,
+            pic_file,
+            format: T2Format::Bit2,
             width_ft: header.width_ft() as f32,
             height_ft: header.height_ft() as f32,
             width: header.width(),
@@ -681,6 +725,20 @@ impl Terrain {
         Ok(())
     }
 
+    pub fn metadata(&self) -> TerrainMetadata {
+        TerrainMetadata {
+            name: self.name.clone(),
+            pic_file: self.pic_file.clone(),
+            format: self.format,
+            width: self.width,
+            height: self.height,
+            width_ft: self.width_ft,
+            height_ft: self.height_ft,
+            origin_latitude: self.origin_latitude,
+            origin_longitude: self.origin_longitude,
+        }
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
@@ -708,6 +766,93 @@ impl Terrain {
     pub fn height(&self) -> u32 {
         self.height
     }
+
+    fn sample_at(&self, xi: u32, zi: u32) -> Sample {
+        self.samples[(zi * self.width + xi) as usize]
+    }
+
+    // Raise or lower individual samples by (x, z, delta), clamping to the range of the
+    // height field. Overrides that fall off the map are ignored.
+    pub fn apply_elevation_overrides(&mut self, overrides: &[(u32, u32, i16)]) {
+        for &(xi, zi, delta) in overrides {
+            if xi >= self.width || zi >= self.height {
+                continue;
+            }
+            let sample = &mut self.samples[(zi * self.width + xi) as usize];
+            sample.height = (i32::from(sample.height) + i32::from(delta))
+                .max(0)
+                .min(255) as u8;
+        }
+    }
+
+    // Build a grid mesh with one vertex per sample, in the same space as generate_skirt. Each
+    // quad takes the color of the sample at its top-left corner.
+    pub fn to_mesh(&self) -> TerrainMesh {
+        let step_x_ft = self.width_ft / self.width.saturating_sub(1).max(1) as f32;
+        let step_z_ft = self.height_ft / self.height.saturating_sub(1).max(1) as f32;
+        let mut positions = Vec::with_capacity(self.samples.len());
+        for zi in 0..self.height {
+            for xi in 0..self.width {
+                let y = f32::from(self.sample_at(xi, zi).height);
+                positions.push([xi as f32 * step_x_ft, y, zi as f32 * step_z_ft]);
+            }
+        }
+        let mut quads = Vec::new();
+        for zi in 0..self.height.saturating_sub(1) {
+            for xi in 0..self.width.saturating_sub(1) {
+                let nw = zi * self.width + xi;
+                let sw = nw + self.width;
+                quads.push(TerrainQuad {
+                    indices: [nw, sw, sw + 1, nw + 1],
+                    color: self.sample_at(xi, zi).color,
+                });
+            }
+        }
+        TerrainMesh { positions, quads }
+    }
+
+    // Build a curtain of vertical quads hanging down from the border of the map, so that
+    // renderers can hide the seam where the terrain ends. Positions are in feet, with x east
+    // and z north from the origin. We do not know the vertical scale of T2 heights yet, so
+    // the top edge is at the raw sample height and the bottom is `depth_ft` below that.
+    pub fn generate_skirt(&self, depth_ft: f32) -> TerrainMesh {
+        if self.width == 0 || self.height == 0 {
+            return TerrainMesh::default();
+        }
+        let mut edges: [Vec<(u32, u32)>; 4] = Default::default();
+        for xi in 0..self.width {
+            edges[0].push((xi, 0));
+            edges[2].push((self.width - 1 - xi, self.height - 1));
+        }
+        for zi in 0..self.height {
+            edges[1].push((self.width - 1, zi));
+            edges[3].push((0, self.height - 1 - zi));
+        }
+
+        let step_x_ft = self.width_ft / self.width.saturating_sub(1).max(1) as f32;
+        let step_z_ft = self.height_ft / self.height.saturating_sub(1).max(1) as f32;
+        let mut positions = Vec::new();
+        let mut quads = Vec::new();
+        for edge in &edges {
+            let base = positions.len() as u32;
+            for &(xi, zi) in edge {
+                let x = xi as f32 * step_x_ft;
+                let z = zi as f32 * step_z_ft;
+                let y = f32::from(self.sample_at(xi, zi).height);
+                positions.push([x, y, z]);
+                positions.push([x, y - depth_ft, z]);
+            }
+            for i in 0..edge.len() as u32 - 1 {
+                let top0 = base + i * 2;
+                let top1 = top0 + 2;
+                quads.push(TerrainQuad {
+                    indices: [top0, top0 + 1, top1 + 1, top1],
+                    color: 0,
+                });
+            }
+        }
+        TerrainMesh { positions, quads }
+    }
 }
 
 #[cfg(test)]
@@ -717,6 +862,95 @@ mod test {
 
     const DUMP: bool = false;
 
+    #[test]
+    fn it_can_generate_a_skirt() {
+        let mut samples = Vec::new();
+        for i in 0..12 {
+            samples.push(Sample::new(0xD2, 0, i * 2));
+        }
+        let terrain = Terrain {
+            name: "Test".to_owned(),
+            pic_file: "TEST.PIC".to_owned(),
+            format: T2Format::Bit2,
+            width: 4,
+            height: 3,
+            width_ft: 300f32,
+            height_ft: 200f32,
+            origin_latitude: 0f32,
+            origin_longitude: 0f32,
+            samples,
+        };
+        let skirt = terrain.generate_skirt(50f32);
+        assert_eq!(skirt.quads.len(), 2 * 3 + 2 * 2);
+        for pair in skirt.positions.chunks(2) {
+            let (top, bottom) = (pair[0], pair[1]);
+            assert_eq!(top[0], bottom[0]);
+            assert_eq!(top[2], bottom[2]);
+            let xi = (top[0] / 100f32) as u32;
+            let zi = (top[2] / 100f32) as u32;
+            assert!(xi == 0 || xi == 3 || zi == 0 || zi == 2);
+            let edge_height = f32::from(terrain.sample_at(xi, zi).height);
+            assert_eq!(top[1], edge_height);
+            assert_eq!(bottom[1], edge_height - 50f32);
+        }
+
+        let empty = Terrain {
+            width: 0,
+            height: 0,
+            samples: Vec::new(),
+            ..terrain
+        };
+        assert!(empty.generate_skirt(50f32).quads.is_empty());
+        let mesh = empty.to_mesh();
+        assert!(mesh.positions.is_empty());
+        assert!(mesh.quads.is_empty());
+    }
+
+    #[test]
+    fn it_can_apply_elevation_overrides() {
+        let mut terrain = Terrain {
+            name: "Test".to_owned(),
+            pic_file: "TEST.PIC".to_owned(),
+            format: T2Format::Bit2,
+            width: 2,
+            height: 2,
+            width_ft: 100f32,
+            height_ft: 100f32,
+            origin_latitude: 0f32,
+            origin_longitude: 0f32,
+            samples: vec![Sample::new(0xD2, 0, 10); 4],
+        };
+        terrain.apply_elevation_overrides(&[(1, 0, 5), (0, 1, -20), (1, 1, 300), (2, 0, 1)]);
+        assert_eq!(terrain.sample_at(0, 0).height, 10);
+        assert_eq!(terrain.sample_at(1, 0).height, 15);
+        assert_eq!(terrain.sample_at(0, 1).height, 0);
+        assert_eq!(terrain.sample_at(1, 1).height, 255);
+
+        // Deltas near the ends of i16 clamp rather than overflow.
+        terrain.apply_elevation_overrides(&[(0, 0, std::i16::MAX), (1, 0, std::i16::MIN)]);
+        assert_eq!(terrain.sample_at(0, 0).height, 255);
+        assert_eq!(terrain.sample_at(1, 0).height, 0);
+    }
+
+    #[test]
+    fn it_can_read_metadata() -> Fallible<()> {
+        let (catalog, inputs) = CatalogBuilder::build_and_select(&["USNF:UKR.T2".to_owned()])?;
+        for &fid in &inputs {
+            let terrain = Terrain::from_bytes(&catalog.read_sync(fid)?)?;
+            let meta = terrain.metadata();
+            assert_eq!(meta.format, T2Format::Bite0);
+            assert_eq!(meta.name, "Ukraine");
+            assert!(meta.pic_file.ends_with(".PIC"));
+            // 13x13 blocks of 16 samples.
+            assert_eq!((meta.width, meta.height), (208, 208));
+            assert_eq!(terrain.samples.len(), 208 * 208);
+            assert!((meta.origin_latitude - 48.50).abs() < std::f32::EPSILON);
+            assert!((meta.origin_longitude - 24.1).abs() < std::f32::EPSILON);
+            assert!(meta.width_ft > 0f32 && meta.height_ft > 0f32);
+        }
+        Ok(())
+    }
+
     #[test]
     fn it_can_parse_all_t2_files() -> Fallible<()> {
         let (mut catalog, inputs) = CatalogBuilder::build_and_select(&["*:*.T2".to_owned()])?;